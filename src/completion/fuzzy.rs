@@ -0,0 +1,117 @@
+// the weights are loosely modelled after fzf, a matched character is always worth something but
+// characters that continue a run or start a word are worth a lot more
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 24;
+const BOUNDARY: i64 = 20;
+const FIRST: i64 = 32;
+const GAP: i64 = 3;
+const LEADING_GAP: i64 = 1;
+
+
+fn cost(weight: i64, count: usize) -> i64 {
+    weight.saturating_mul(i64::try_from(count).unwrap_or(i64::MAX))
+}
+
+fn is_boundary(text: &[char], idx: usize) -> bool {
+    if idx == 0 {
+        return true;
+    }
+
+    let previous = text[idx - 1];
+    let current = text[idx];
+
+    matches!(previous, '/' | '-' | '_' | '.' | ' ' | '$')
+        || (previous.is_lowercase() && current.is_uppercase())
+        || (!previous.is_ascii_digit() && current.is_ascii_digit())
+}
+
+fn eq(a: char, b: char, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        a == b
+    } else {
+        a.to_lowercase().eq(b.to_lowercase())
+    }
+}
+
+// score returns None if the pattern isnt a subsequence of the text, otherwise a higher score
+// means a better match, the pattern only matches case sensitively when it contains uppercase
+pub fn score(pattern: &str, text: &str) -> Option<i64> {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    let case_sensitive = pattern.iter().any(|character| character.is_uppercase());
+
+    // best[j] is the best score of the pattern so far where the last matched character is text[j]
+    let mut best: Vec<Option<i64>> = vec![None; text.len()];
+
+    for (i, &wanted) in pattern.iter().enumerate() {
+        let mut next: Vec<Option<i64>> = vec![None; text.len()];
+
+        for j in 0..text.len() {
+            if !eq(wanted, text[j], case_sensitive) {
+                continue;
+            }
+
+            let mut bonus = MATCH;
+            if is_boundary(&text, j) {
+                bonus += BOUNDARY;
+            }
+
+            let score = if i == 0 {
+                // skipping characters before the first match costs less than gaps inside it
+                let first = if j == 0 { FIRST } else { 0 };
+                Some(bonus + first - cost(LEADING_GAP, j))
+            } else {
+                (0..j).filter_map(|k| {
+                    let previous = best[k]?;
+                    if k + 1 == j {
+                        Some(previous + bonus + CONSECUTIVE)
+                    } else {
+                        Some(previous + bonus - cost(GAP, j - k - 1))
+                    }
+                }).max()
+            };
+
+            next[j] = score;
+        }
+
+        best = next;
+    }
+
+    best.into_iter().flatten().max()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_subsequences_match() {
+        assert!(score("gco", "git-checkout").is_some());
+        assert!(score("ocg", "git-checkout").is_none());
+        assert!(score("abc", "ab").is_none());
+        assert_eq!(score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn uppercase_makes_it_case_sensitive() {
+        assert!(score("read", "README.md").is_some());
+        assert!(score("READ", "README.md").is_some());
+        assert!(score("Read", "README.md").is_none());
+    }
+
+    #[test]
+    fn better_matches_score_higher() {
+        // a prefix beats the same characters further in, and a run beats scattered characters
+        assert!(score("car", "cargo") > score("car", "scar"));
+        assert!(score("rgo", "rgox") > score("rgo", "rxgxo"));
+        // word starts count more than characters inside a word
+        assert!(score("fb", "foo_bar") > score("fb", "fabric"));
+        assert!(score("tm", "toMarkdown") > score("tm", "atom"));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio, Output};
+use std::os::unix::process::CommandExt;
+use std::time::{Duration, SystemTime};
+use std::sync::mpsc;
+use std::thread;
+use std::env;
+use std::fs;
+
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+
+
+// commands run for completion that take longer than this are killed and offer nothing
+const TIMEOUT: Duration = Duration::from_millis(1500);
+
+// help output past this size is ignored, no sane help text is anywhere near it
+const MAX_OUTPUT: usize = 512 * 1024;
+
+
+// HelpCache remembers the options parsed from `<command> --help`, an entry is only reused while
+// the binary keeps the same modification time
+#[derive(Default)]
+pub struct HelpCache {
+    options: HashMap<PathBuf, (SystemTime, Vec<(String, String)>)>,
+}
+
+impl HelpCache {
+    pub fn new() -> HelpCache {
+        HelpCache {
+            options: HashMap::new(),
+        }
+    }
+
+    pub fn options(&mut self, path: &Path) -> &[(String, String)] {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let stale = self.options.get(path).is_none_or(|(time, _)| *time != modified);
+        if stale {
+            let options = run(path).map(|output| parse(&output)).unwrap_or_default();
+            self.options.insert(path.to_path_buf(), (modified, options));
+        }
+
+        &self.options[path].1
+    }
+}
+
+// output runs a command for completion without stdin in a process group of its own, the whole
+// group is killed when it takes longer than TIMEOUT so nothing it started is left running
+pub fn output(command: &mut Command) -> Option<Output> {
+    let child = command.stdin(Stdio::null()).process_group(0).spawn().ok()?;
+    let group = Pid::from_raw(i32::try_from(child.id()).ok()?);
+
+    let (sender, receiver) = mpsc::channel::<std::io::Result<Output>>();
+    thread::spawn(move || {
+        let _ = sender.send(child.wait_with_output());
+    });
+
+    if let Ok(output) = receiver.recv_timeout(TIMEOUT) {
+        return output.ok();
+    }
+
+    let _ = signal::killpg(group, Signal::SIGKILL);
+    None
+}
+
+// run executes the command with `--help` away from the users files and terminal, it gets a
+// scratch working directory and a minimal environment, the path has to be absolute since the
+// working directory isnt the one of the shell
+fn run(path: &Path) -> Option<String> {
+    let output = output(Command::new(path)
+        .arg("--help")
+        .current_dir(env::temp_dir())
+        .env_clear()
+        .env("PATH", env::var_os("PATH").unwrap_or_default())
+        .env("TERM", "dumb")
+        .env("PAGER", "cat")
+        .env("MANPAGER", "cat")
+        .env("GIT_PAGER", "cat")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped()))?;
+
+    // plenty of programs print their help on stderr
+    let mut bytes = output.stdout;
+    if bytes.is_empty() {
+        bytes = output.stderr;
+    }
+    bytes.truncate(MAX_OUTPUT);
+
+    Some(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn is_option(word: &str) -> bool {
+    let name = word.trim_start_matches('-');
+    let dashes = word.len() - name.len();
+
+    (dashes == 1 || dashes == 2)
+        && name.chars().next().is_some_and(char::is_alphanumeric)
+        && name.chars().all(|character| character.is_alphanumeric() || character == '-' || character == '_')
+}
+
+// parse picks the options out of help text, it understands the common layout of one or more
+// comma separated options at the start of a line followed by a description after a wide gap
+fn parse(help: &str) -> Vec<(String, String)> {
+    let mut options: Vec<(String, String)> = Vec::new();
+    let lines = help.lines().collect::<Vec<&str>>();
+
+    for (idx, line) in lines.iter().enumerate() {
+        let line = line.trim_start();
+        if !line.starts_with('-') {
+            continue;
+        }
+
+        let (names, description) = match line.find("  ").or_else(|| line.find('\t')) {
+            Some(gap) => (&line[..gap], line[gap..].trim()),
+            None => (line, ""),
+        };
+
+        // some programs put the description on the next line instead
+        let mut description = description.to_string();
+        if description.is_empty() {
+            if let Some(next) = lines.get(idx + 1) {
+                let next = next.trim();
+                if !next.is_empty() && !next.starts_with('-') {
+                    description = next.to_string();
+                }
+            }
+        }
+
+        for name in names.split([',', ' ', '/']) {
+            // strip argument placeholders like `--color=WHEN` or `--jobs[=N]`
+            let name = name.split(['=', '[', '<']).next().unwrap_or_default();
+            let name = name.trim_end_matches("...");
+
+            if is_option(name) && !options.iter().any(|(option, _)| option == name) {
+                options.push((name.to_string(), description.clone()));
+            }
+        }
+    }
+
+    options
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(options: &[(String, String)]) -> Vec<&str> {
+        options.iter().map(|(option, _)| option.as_str()).collect()
+    }
+
+    #[test]
+    fn short_and_long_names_share_a_description() {
+        let options = parse("  -a, --all                  do not ignore entries starting with .\n");
+        assert_eq!(options, [
+            (String::from("-a"), String::from("do not ignore entries starting with .")),
+            (String::from("--all"), String::from("do not ignore entries starting with .")),
+        ]);
+    }
+
+    #[test]
+    fn argument_placeholders_are_stripped() {
+        let options = parse("      --color[=WHEN]         color the output\n  -w, --width=COLS           set output width\n  -I, --ignore <PATTERN>     skip entries\n");
+        assert_eq!(names(&options), ["--color", "-w", "--width", "-I", "--ignore"]);
+        assert_eq!(options[2].1, "set output width");
+    }
+
+    #[test]
+    fn descriptions_can_be_on_the_next_line() {
+        let options = parse("  --verbose\n      print every step\n  --quiet\n  --dry-run  do nothing\n");
+        assert_eq!(options, [
+            (String::from("--verbose"), String::from("print every step")),
+            (String::from("--quiet"), String::new()),
+            (String::from("--dry-run"), String::from("do nothing")),
+        ]);
+    }
+
+    #[test]
+    fn other_lines_are_not_options() {
+        let help = "Usage: ls [OPTION]... [FILE]...\nList information about the FILEs.\n\n  ---------\n  - a list item\n  -->  arrow\n  -1                         one file per line\n  -1                         again\n";
+        assert_eq!(names(&parse(help)), ["-1"]);
+    }
+}
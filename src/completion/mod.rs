@@ -1,36 +1,480 @@
+mod fuzzy;
+mod help;
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::fmt;
 use std::fs;
 use std::env;
 
+use crate::config::Config;
+use crate::hash::{self, CommandHash};
+use crate::lexer;
+
+pub use help::HelpCache;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    // value is what gets inserted into the buffer and display is what the menu shows
+    pub value: String,
+    pub display: String,
+    pub description: Option<String>,
+}
+
+impl Candidate {
+    fn new(value: String) -> Candidate {
+        Candidate {
+            display: value.clone(),
+            value,
+            description: None,
+        }
+    }
+}
+
+pub struct Completion {
+    // start is the byte index in the line where the completed word starts
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
 
-fn as_local(global: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let current_dir = env::current_dir()?;
-    let mut local = global.to_string();
+// Matching decides how the typed word is compared to the candidates, it is changed with
+// `set completion <prefix|fuzzy>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Matching {
+    Prefix,
+    Fuzzy,
+}
 
-    for _ in 0..current_dir.to_str().expect("str convert failed").len() + 1 {
-        local.remove(0);
+impl fmt::Display for Matching {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Matching::Prefix => write!(f, "prefix"),
+            Matching::Fuzzy => write!(f, "fuzzy"),
+        }
     }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Words(Vec<String>),
+    // Command is run with the command name, the current word and the previous word as arguments
+    // and every line it prints is a candidate
+    Command(String),
+}
 
-    Ok(local)
+// Spec is registered with the `complete` builtin and tells completion what the arguments of a
+// command can be
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spec {
+    pub command: String,
+    // position is the argument the spec applies to starting at 1, None applies to all of them
+    pub position: Option<usize>,
+    pub source: Source,
 }
 
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "complete")?;
 
-pub fn complete(original_path: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let current_dir = env::current_dir()?;
+        if let Some(position) = self.position {
+            write!(f, " -p {position}")?;
+        }
+
+        match &self.source {
+            Source::Words(words) => write!(f, " -W \"{}\"", words.join(" "))?,
+            Source::Command(command) => write!(f, " -C \"{command}\"")?,
+        }
+
+        write!(f, " {}", self.command)
+    }
+}
+
+// Context describes what kind of word is being completed
+#[derive(Debug, PartialEq)]
+enum Context {
+    Command,
+    Variable,
+    Argument,
+}
 
-    for dir in fs::read_dir(current_dir)? {
-        let path = dir?.path();
+// words splits the command being typed into words the way the shell reads them, every word has
+// the byte index it starts at and its text with the quotes and escapes taken out. the last word is
+// the one under the cursor and it is empty when the line ends with a separator
+fn words(line: &str) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
 
-        let path_str = as_local(path
-                                .as_os_str()
-                                .to_str()
-                                .unwrap())?;
+    for (idx, character) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            word.push(character);
+            continue;
+        }
 
-        if path_str.starts_with(original_path) {
-            return Ok(path_str);
+        match character {
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if start != idx {
+                    words.push((start, std::mem::take(&mut word)));
+                }
+                start = idx + 1;
+            },
+            '&' | '|' | '\n' if !quoted => {
+                words.clear();
+                word.clear();
+                start = idx + 1;
+            },
+            _ => word.push(character),
         }
     }
 
-    Ok(original_path.to_string())
+    words.push((start, word));
+    words
+}
+
+// word_start returns the byte index where the word under the cursor starts, separators inside
+// double quotes are part of the word
+pub fn word_start(line: &str) -> usize {
+    words(line).last().map_or(0, |(start, _)| *start)
+}
+
+// arguments returns the words of the command being typed that come before the word at start
+fn arguments(line: &str, start: usize) -> Vec<String> {
+    let mut words = words(&line[..start]);
+    words.pop();
+    words.into_iter().map(|(_, word)| word).collect()
 }
 
+fn context(line: &str, start: usize) -> Context {
+    if line[start..].starts_with('$') {
+        return Context::Variable;
+    }
+
+    // the word is a command if nothing but whitespace separates it from the start of the line or
+    // the previous command separator
+    let before = line[..start].trim_end();
+    let is_path = line[start..].contains(['/', '~', '"']);
+    if !is_path && (before.is_empty() || before.ends_with(['&', '|', '\n'])) {
+        Context::Command
+    } else {
+        Context::Argument
+    }
+}
+
+fn commands(hash: &mut CommandHash, config: &Config) -> Vec<Candidate> {
+    let mut candidates: Vec<String> = Vec::new();
+
+    for keyword in lexer::KEYWORDS {
+        candidates.push(keyword.to_string());
+    }
+
+    for alias in &config.alias {
+        candidates.push(alias.0.as_string());
+    }
+
+    candidates.extend(hash.names().iter().cloned());
+
+    candidates.into_iter().map(Candidate::new).collect()
+}
+
+fn variables() -> Vec<Candidate> {
+    // variables are written as `$NAME$` so the closing dollar is appended to the completion
+    env::vars_os()
+        .filter_map(|(key, _)| key.into_string().ok())
+        .map(|key| Candidate::new(format!("${key}$")))
+        .collect()
+}
+
+// quote wraps a path in double quotes if the lexer would otherwise split it, a directory is left
+// open so that completing inside it keeps the word quoted
+fn quote(path: &str, is_dir: bool) -> String {
+    if !path.contains([' ', '&', '|', '=', '"', '$', '\\', '\n']) {
+        return path.to_string();
+    }
+
+    // `~` isnt expanded inside of strings so a path that has to be quoted gets the home directory
+    let path = match (path.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.starts_with('/') => home + rest,
+        _ => path.to_string(),
+    };
+
+    let escaped = path.replace('\\', "\\\\").replace('"', "\\\"");
+    if is_dir {
+        format!("\"{escaped}")
+    } else {
+        format!("\"{escaped}\"")
+    }
+}
+
+// unquote undoes quote, a word is read the same way here as when the line is split into words
+fn unquote(word: &str) -> String {
+    words(word).pop().map(|(_, word)| word).unwrap_or_default()
+}
+
+// expand_home turns the `~` prefix the user typed into the home directory so it can be read
+fn expand_home(dir: &str) -> PathBuf {
+    if dir == "~" || dir.starts_with("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(dir.trim_start_matches('~').trim_start_matches('/'));
+        }
+    }
+
+    if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        PathBuf::from(dir)
+    }
+}
+
+// split_path splits an unquoted word into the directory part exactly as it was typed and the name
+// that is being completed inside of it
+fn split_path(word: &str) -> (&str, &str) {
+    match word.rfind('/') {
+        Some(idx) => word.split_at(idx + 1),
+        None => ("", word),
+    }
+}
+
+fn files(word: &str) -> Result<Vec<Candidate>, Box<dyn std::error::Error>> {
+    let word = unquote(word);
+
+    if word == "~" {
+        return Ok(vec![Candidate::new(String::from("~/"))]);
+    }
 
+    // the directory part is kept exactly as typed so that `~/`, `./` and relative prefixes survive
+    let (dir, name) = split_path(&word);
+
+    let mut candidates: Vec<Candidate> = Vec::new();
+
+    // a directory that doesnt exist (yet) simply has nothing to complete
+    let Ok(entries) = fs::read_dir(expand_home(dir)) else {
+        return Ok(candidates);
+    };
+
+    for entry in entries {
+        let entry = entry?;
+
+        // names that arent utf8 cant be represented in the line buffer so they are skipped
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        // hidden files are only offered when the user asks for them
+        if file_name.starts_with('.') && !name.starts_with('.') {
+            continue;
+        }
+
+        let is_dir = entry.path().is_dir();
+        let suffix = if is_dir { "/" } else { "" };
+
+        candidates.push(Candidate {
+            value: quote(&format!("{dir}{file_name}{suffix}"), is_dir),
+            display: format!("{file_name}{suffix}"),
+            description: None,
+        });
+    }
+
+    Ok(candidates)
+}
+
+fn spec_output(command: &str, args: &[String]) -> Vec<String> {
+    let mut words = command.split_whitespace();
+    let Some(program) = words.next() else {
+        return Vec::new();
+    };
+
+    let output = help::output(Command::new(program)
+        .args(words)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null()));
+
+    match output {
+        Some(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// specs looks for a spec registered for the command and position of the word, None means that no
+// spec applies and the word should be completed as a file
+fn specs(line: &str, start: usize, config: &Config) -> Option<Vec<Candidate>> {
+    let words = arguments(line, start);
+    let command = words.first()?;
+    let position = words.len();
+    let word = &line[start..];
+
+    let spec = config.completions.iter()
+        .filter(|spec| &spec.command == command)
+        .find(|spec| spec.position.is_none_or(|pos| pos == position))?;
+
+    let candidates = match &spec.source {
+        Source::Words(words) => words.clone(),
+        Source::Command(program) => {
+            let previous = words.last().cloned().unwrap_or_default();
+            spec_output(program, &[command.clone(), word.to_string(), previous])
+        },
+    };
+
+    Some(candidates.into_iter().map(Candidate::new).collect())
+}
+
+// options offers the flags found in the help output of commands in $PATH that have no spec
+fn options(line: &str, start: usize, hash: &CommandHash, help: &mut HelpCache) -> Vec<Candidate> {
+    let words = arguments(line, start);
+    let Some(command) = words.first() else {
+        return Vec::new();
+    };
+
+    // only commands found through $PATH are run, a command given as a path could be any script
+    // that was just cloned or downloaded
+    if command.contains('/') {
+        return Vec::new();
+    }
+
+    let path = hash.get(command).cloned().or_else(|| CommandHash::search(command));
+
+    // a relative directory in $PATH would be looked up in the scratch directory help runs in
+    let Some(path) = path.filter(|path| path.is_absolute() && hash::is_executable(path)) else {
+        return Vec::new();
+    };
+
+    help.options(&path).iter()
+        .map(|(option, description)| Candidate {
+            value: option.clone(),
+            display: option.clone(),
+            description: (!description.is_empty()).then(|| description.clone()),
+        })
+        .collect()
+}
+
+fn score(pattern: &str, text: &str, matching: Matching) -> Option<i64> {
+    match matching {
+        Matching::Prefix => text.starts_with(pattern).then_some(0),
+        Matching::Fuzzy => fuzzy::score(pattern, text),
+    }
+}
+
+// common_prefix returns the longest prefix shared by every candidate
+pub fn common_prefix(candidates: &[Candidate]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+
+    let mut prefix = first.value.as_str();
+    for candidate in &candidates[1..] {
+        let len = prefix.char_indices()
+            .zip(candidate.value.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(candidate.value.len()), |((idx, _), _)| idx);
+
+        prefix = &prefix[..len];
+    }
+
+    prefix.to_string()
+}
+
+// complete takes the line up to the cursor and returns every candidate for the last word, the
+// candidates are ranked by how well they match when fuzzy matching is on
+pub fn complete(line: &str, hash: &mut CommandHash, config: &Config, help: &mut HelpCache) -> Result<Completion, Box<dyn std::error::Error>> {
+    let start = word_start(line);
+    let word = &line[start..];
+
+    // files are matched on their name while everything else is matched on the whole word
+    let unquoted = unquote(word);
+    let mut pattern = word;
+
+    let candidates = match context(line, start) {
+        Context::Command => commands(hash, config),
+        Context::Variable => variables(),
+        Context::Argument => {
+            // a spec wins over the help output so help is only run for commands without one
+            if let Some(candidates) = specs(line, start, config) {
+                candidates
+            } else {
+                let options = if word.starts_with('-') {
+                    options(line, start, hash, help)
+                } else {
+                    Vec::new()
+                };
+
+                if options.is_empty() {
+                    pattern = split_path(&unquoted).1;
+                    files(word)?
+                } else {
+                    options
+                }
+            }
+        },
+    };
+
+    let mut candidates = candidates.into_iter()
+        .filter_map(|candidate| Some((score(pattern, &candidate.display, config.matching)?, candidate)))
+        .collect::<Vec<(i64, Candidate)>>();
+
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.value.cmp(&b.1.value)));
+    candidates.dedup_by(|a, b| a.1 == b.1);
+
+    Ok(Completion {
+        start,
+        candidates: candidates.into_iter().map(|(_, candidate)| candidate).collect(),
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::parser::{Node, Value};
+
+    #[test]
+    fn unquote_undoes_quote() {
+        for path in ["plain", "with space", "say \"hi\"", "back\\slash", "a\\\"b", "new\nline"] {
+            assert_eq!(unquote(&quote(path, false)), path);
+            assert_eq!(unquote(&quote(path, true)), path);
+        }
+    }
+
+    #[test]
+    fn quoted_home_paths_are_expanded() {
+        let Ok(home) = env::var("HOME") else {
+            return;
+        };
+
+        let value = quote("~/My Docs/notes.txt", false);
+        let tokens = lexer::tokenize(&format!("cat {value}\n")).unwrap();
+        let mut errors = crate::NshErrorType::new();
+        let mut ast = crate::parser::Ast::new(&tokens, &mut errors);
+
+        match ast.parse(&Config::new()) {
+            [Node::Exec { args, .. }] => assert_eq!(args, &[Value::Str(format!("{home}/My Docs/notes.txt"))]),
+            nodes => panic!("expected one command but got {nodes:?}"),
+        }
+        assert_eq!(quote("~/plain", false), "~/plain");
+    }
+
+    #[test]
+    fn escaped_quotes_stay_in_the_word() {
+        let line = "git commit -m \"say \\\"a | b\\\"\" --am";
+        let start = word_start(line);
+
+        assert_eq!(&line[start..], "--am");
+        assert_eq!(arguments(line, start), ["git", "commit", "-m", "say \"a | b\""]);
+    }
+
+    #[test]
+    fn separators_start_a_new_command() {
+        assert_eq!(word_start("ls | gr"), 5);
+        assert_eq!(arguments("ls -l && cd ", 12), ["cd"]);
+        assert_eq!(word_start("echo "), 5);
+    }
+}
@@ -1,7 +1,27 @@
 use lib_lexin::Token;
 
+use crate::completion::{Spec, Matching};
+use crate::readline::{Editing, Theme};
+
 pub struct Config {
     pub alias: Vec<(Token, Vec<Token>)>,
+    pub completions: Vec<Spec>,
+    pub matching: Matching,
+    // history_size is how many entries are kept in memory and in the history file
+    pub history_size: usize,
+    // share_history takes in the entries other sessions append to the history file at every prompt
+    pub share_history: bool,
+    pub editing: Editing,
+    // the vi indicators are shown in front of the prompt in insert and normal mode
+    pub vi_insert_indicator: String,
+    pub vi_normal_indicator: String,
+    // highlight colours the line while it is typed with the colours of the theme
+    pub highlight: bool,
+    // highlight_paste marks what was just pasted with the paste colour of the theme
+    pub highlight_paste: bool,
+    pub theme: Theme,
+    // prompt_placeholder is shown in place of the parts of the prompt that are still running
+    pub prompt_placeholder: String,
 }
 
 
@@ -9,7 +29,97 @@ impl Config {
     pub fn new() -> Config {
         Config {
             alias: Vec::new(),
+            completions: Vec::new(),
+            matching: Matching::Prefix,
+            history_size: 10000,
+            share_history: false,
+            editing: Editing::Emacs,
+            vi_insert_indicator: String::from("[I] "),
+            vi_normal_indicator: String::from("[N] "),
+            highlight: true,
+            highlight_paste: true,
+            theme: Theme::new(),
+            prompt_placeholder: String::from("…"),
+        }
+    }
+
+    // set changes an option from conf.nsh or the prompt with `set <Option> <Value>`
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            "completion" => {
+                self.matching = match value {
+                    "prefix" => Matching::Prefix,
+                    "fuzzy" => Matching::Fuzzy,
+                    _ => return Err(format!("completion expects `prefix` or `fuzzy` but got `{value}`")),
+                };
+            },
+            "history_size" => {
+                self.history_size = value.parse()
+                    .map_err(|_| format!("history_size expects a number but got `{value}`"))?;
+            },
+            "share_history" => {
+                self.share_history = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("share_history expects `on` or `off` but got `{value}`")),
+                };
+            },
+            "editing" => {
+                self.editing = match value {
+                    "emacs" => Editing::Emacs,
+                    "vi" => Editing::Vi,
+                    _ => return Err(format!("editing expects `emacs` or `vi` but got `{value}`")),
+                };
+            },
+            "vi_insert_indicator" => self.vi_insert_indicator = value.to_string(),
+            "vi_normal_indicator" => self.vi_normal_indicator = value.to_string(),
+            "highlight" => {
+                self.highlight = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("highlight expects `on` or `off` but got `{value}`")),
+                };
+            },
+            "highlight_paste" => {
+                self.highlight_paste = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("highlight_paste expects `on` or `off` but got `{value}`")),
+                };
+            },
+            "color_command" => self.theme.command = Theme::style(value)?,
+            "color_unknown" => self.theme.unknown = Theme::style(value)?,
+            "color_string" => self.theme.string = Theme::style(value)?,
+            "color_variable" => self.theme.variable = Theme::style(value)?,
+            "color_operator" => self.theme.operator = Theme::style(value)?,
+            "color_path" => self.theme.path = Theme::style(value)?,
+            "color_paste" => self.theme.paste = Theme::style(value)?,
+            "prompt_placeholder" => self.prompt_placeholder = value.to_string(),
+            _ => return Err(format!("unknown option `{option}`")),
         }
+
+        Ok(())
+    }
+
+    pub fn options(&self) -> Vec<(&str, String)> {
+        vec![
+            ("completion", self.matching.to_string()),
+            ("history_size", self.history_size.to_string()),
+            ("share_history", String::from(if self.share_history { "on" } else { "off" })),
+            ("editing", self.editing.to_string()),
+            ("vi_insert_indicator", format!("{:?}", self.vi_insert_indicator)),
+            ("vi_normal_indicator", format!("{:?}", self.vi_normal_indicator)),
+            ("highlight", String::from(if self.highlight { "on" } else { "off" })),
+            ("highlight_paste", String::from(if self.highlight_paste { "on" } else { "off" })),
+            ("color_command", self.theme.command.clone()),
+            ("color_unknown", self.theme.unknown.clone()),
+            ("color_string", self.theme.string.clone()),
+            ("color_variable", self.theme.variable.clone()),
+            ("color_operator", self.theme.operator.clone()),
+            ("color_path", self.theme.path.clone()),
+            ("color_paste", self.theme.paste.clone()),
+            ("prompt_placeholder", format!("{:?}", self.prompt_placeholder)),
+        ]
     }
 }
 
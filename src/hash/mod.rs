@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::env;
+use std::fs;
+
+
+// the command hash remembers where executables live so that running a command doesnt rescan
+// every directory in $PATH, it also keeps an index of every executable name in $PATH which is
+// used by command completion
+pub struct CommandHash {
+    table: HashMap<String, (PathBuf, usize)>,
+    index: Option<Vec<String>>,
+}
+
+impl Default for CommandHash {
+    fn default() -> Self {
+        CommandHash::new()
+    }
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+fn path_dirs() -> Vec<PathBuf> {
+    match env::var_os("PATH") {
+        Some(path) => env::split_paths(&path).collect(),
+        None => Vec::new(),
+    }
+}
+
+impl CommandHash {
+    pub fn new() -> CommandHash {
+        CommandHash {
+            table: HashMap::new(),
+            index: None,
+        }
+    }
+
+    // clear is called when $PATH is reassigned or the user runs `hash -r`
+    pub fn clear(&mut self) {
+        self.table.clear();
+        self.index = None;
+    }
+
+    pub fn search(name: &str) -> Option<PathBuf> {
+        for dir in path_dirs() {
+            let path = dir.join(name);
+            if is_executable(&path) {
+                return Some(path);
+            }
+        }
+
+        None
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Option<PathBuf> {
+        // names containing a slash are paths and are never looked up in $PATH
+        if name.contains('/') {
+            return Some(PathBuf::from(name));
+        }
+
+        if let Some((path, hits)) = self.table.get_mut(name) {
+            // the executable may have been removed since it was hashed
+            if is_executable(path) {
+                *hits += 1;
+                return Some(path.clone());
+            }
+            self.table.remove(name);
+        }
+
+        let path = CommandHash::search(name)?;
+        self.table.insert(name.to_string(), (path.clone(), 1));
+
+        Some(path)
+    }
+
+    // remember inserts a command into the table without counting it as a hit, like `hash name`
+    pub fn remember(&mut self, name: &str) -> Option<PathBuf> {
+        let path = CommandHash::search(name)?;
+        self.table.insert(name.to_string(), (path.clone(), 0));
+        Some(path)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PathBuf> {
+        self.table.get(name).map(|(path, _)| path)
+    }
+
+    pub fn entries(&self) -> Vec<(&String, &PathBuf, usize)> {
+        let mut entries = self.table.iter()
+            .map(|(name, (path, hits))| (name, path, *hits))
+            .collect::<Vec<(&String, &PathBuf, usize)>>();
+
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    // names returns every executable in $PATH, the directories are only scanned the first time
+    // this is called after the hash has been cleared
+    pub fn names(&mut self) -> &[String] {
+        self.index.get_or_insert_with(|| {
+            let mut names: Vec<String> = Vec::new();
+
+            for dir in path_dirs() {
+                let Ok(entries) = fs::read_dir(&dir) else {
+                    continue;
+                };
+
+                for entry in entries.flatten() {
+                    if let Some(name) = entry.file_name().to_str() {
+                        if is_executable(&entry.path()) {
+                            names.push(name.to_string());
+                        }
+                    }
+                }
+            }
+
+            names.sort();
+            names.dedup();
+            names
+        })
+    }
+}
@@ -0,0 +1,430 @@
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+
+use nix::fcntl::{flock, FlockArg};
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub line: String,
+    // cwd is the directory the line was run in, entries loaded from an old history file dont
+    // have one and neither do they have any of the fields below
+    pub cwd: Option<String>,
+    // timestamp is when the line was started in seconds since the unix epoch
+    pub timestamp: Option<u64>,
+    // duration is how long the line ran for in milliseconds
+    pub duration: Option<u64>,
+    pub status: Option<i32>,
+}
+
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::new();
+    let mut characters = field.chars();
+
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            unescaped.push(character);
+            continue;
+        }
+
+        match characters.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+impl Entry {
+    pub fn new(line: &str) -> Entry {
+        Entry {
+            line: line.to_string(),
+            ..Entry::default()
+        }
+    }
+
+    // the extended format is `: <timestamp>\t<duration>\t<status>\t<cwd>\t<line>` with tabs,
+    // newlines and backslashes escaped, records that dont follow it are plain lines from an older
+    // history file
+    fn parse(record: &str) -> Entry {
+        let fields = record.strip_prefix(": ")
+            .map(|fields| fields.splitn(5, '\t').collect::<Vec<&str>>());
+
+        match fields.as_deref() {
+            Some([timestamp, duration, status, cwd, line]) => Entry {
+                line: unescape(line),
+                cwd: (!cwd.is_empty()).then(|| unescape(cwd)),
+                timestamp: timestamp.parse().ok(),
+                duration: duration.parse().ok(),
+                status: status.parse().ok(),
+            },
+            _ => Entry::new(record),
+        }
+    }
+
+    fn record(&self) -> String {
+        let field = |value: Option<String>| value.unwrap_or_default();
+
+        format!(
+            ": {}\t{}\t{}\t{}\t{}\n",
+            field(self.timestamp.map(|timestamp| timestamp.to_string())),
+            field(self.duration.map(|duration| duration.to_string())),
+            field(self.status.map(|status| status.to_string())),
+            escape(self.cwd.as_deref().unwrap_or_default()),
+            escape(&self.line),
+        )
+    }
+}
+
+// open opens the history file and locks it, the lock is released when the file is dropped so
+// other sessions never see a half written file
+fn open(path: &str, exclusive: bool) -> Result<File, Box<dyn std::error::Error>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+
+    let arg = if exclusive { FlockArg::LockExclusive } else { FlockArg::LockShared };
+    flock(file.as_raw_fd(), arg)?;
+
+    Ok(file)
+}
+
+fn read(mut file: &File) -> Result<String, Box<dyn std::error::Error>> {
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut bytes)?;
+
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+pub struct History {
+    entries: Vec<Entry>,
+    // offset is how much of the history file this session has read or written and last is the
+    // final record in that part, together they tell which records other sessions appended since
+    offset: usize,
+    last: Option<String>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            entries: Vec::new(),
+            offset: 0,
+            last: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    // clear forgets every entry and empties the history file
+    pub fn clear(&mut self, path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        self.entries.clear();
+
+        match path {
+            Some(path) => self.rewrite(path, Vec::clear),
+            None => Ok(()),
+        }
+    }
+
+    // remove forgets the entry at index and takes its record out of the history file, it tells if
+    // there was such an entry
+    pub fn remove(&mut self, path: Option<&str>, index: usize) -> Result<bool, Box<dyn std::error::Error>> {
+        if index >= self.entries.len() {
+            return Ok(false);
+        }
+        let entry = self.entries.remove(index);
+
+        if let Some(path) = path {
+            self.rewrite(path, |records| {
+                if let Some(position) = records.iter().rposition(|record| Entry::parse(record) == entry) {
+                    records.remove(position);
+                }
+            })?;
+        }
+
+        Ok(true)
+    }
+
+    fn trim(&mut self, size: usize) {
+        if self.entries.len() > size {
+            self.entries.drain(..self.entries.len() - size);
+        }
+    }
+
+    // ignored tells if an entry shouldnt be remembered, lines starting with a space are kept
+    // private and a line that is the same as the one before it is a duplicate
+    pub fn ignored(&self, entry: &Entry) -> bool {
+        entry.line.trim().is_empty()
+            || entry.line.starts_with(' ')
+            || self.entries.last().is_some_and(|last| last.line == entry.line)
+    }
+
+    // add remembers an entry unless it is ignored, only the newest size entries are kept
+    pub fn add(&mut self, entry: Entry, size: usize) -> bool {
+        if self.ignored(&entry) || size == 0 {
+            return false;
+        }
+
+        self.entries.push(entry);
+        self.trim(size);
+        true
+    }
+
+    fn seen(&mut self, content: &str) {
+        self.offset = content.len();
+        self.last = content.lines().last().map(str::to_string);
+    }
+
+    // unseen returns the records in the history file that this session hasnt seen yet, when the
+    // file was trimmed by another session the records after the last one seen are new instead
+    fn unseen<'a>(&self, content: &'a str) -> &'a str {
+        let Some(last) = &self.last else {
+            return content.get(self.offset..).unwrap_or(content);
+        };
+
+        let untouched = content.get(..self.offset)
+            .is_some_and(|seen| seen.trim_end_matches('\n').ends_with(last.as_str()));
+
+        if untouched {
+            return &content[self.offset..];
+        }
+
+        match content.rfind(&(last.clone() + "\n")) {
+            Some(position) => &content[position + last.len() + 1..],
+            None => "",
+        }
+    }
+
+    pub fn load(&mut self, path: &str, size: usize) -> Result<(), Box<dyn std::error::Error>> {
+        // the config directory might not be there yet on a fresh system
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = open(path, false)?;
+        let content = read(&file)?;
+
+        for record in content.lines() {
+            self.entries.push(Entry::parse(record));
+        }
+        self.trim(size);
+        self.seen(&content);
+
+        Ok(())
+    }
+
+    // update adds the entries other sessions appended to the history file since it was last read
+    // or written by this session
+    pub fn update(&mut self, path: &str, size: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let file = open(path, false)?;
+        let content = read(&file)?;
+
+        for record in self.unseen(&content).lines() {
+            self.add(Entry::parse(record), size);
+        }
+        self.seen(&content);
+
+        Ok(())
+    }
+
+    // rewrite changes the records of the history file that this session has seen while it holds
+    // the lock, the file is read again first so the records other sessions appended since are
+    // written back after them and are still new to this session afterwards
+    fn rewrite(&mut self, path: &str, change: impl FnOnce(&mut Vec<String>)) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = open(path, true)?;
+        let content = read(&file)?;
+
+        let unseen = self.unseen(&content);
+        let mut records = content[..content.len() - unseen.len()].lines().map(str::to_string).collect::<Vec<String>>();
+        change(&mut records);
+
+        let seen = records.into_iter().map(|record| record + "\n").collect::<String>();
+        file.set_len(0)?;
+        file.write_all(seen.as_bytes())?;
+        file.write_all(unseen.as_bytes())?;
+        self.seen(&seen);
+
+        Ok(())
+    }
+
+    // append writes one entry to the end of the history file and returns the entries other
+    // sessions appended before it, once the file has grown a tenth past size it is rewritten with
+    // only the newest size entries
+    pub fn append(&mut self, path: &str, entry: &Entry, size: usize) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+        let mut file = open(path, true)?;
+
+        let mut content = read(&file)?;
+        let unseen = self.unseen(&content).lines().map(Entry::parse).collect::<Vec<Entry>>();
+
+        let record = entry.record();
+        file.write_all(record.as_bytes())?;
+        content.push_str(&record);
+
+        let count = content.lines().count();
+        if count > size + size / 10 {
+            content = content.lines().skip(count - size).map(|record| record.to_string() + "\n").collect();
+
+            file.set_len(0)?;
+            file.write_all(content.as_bytes())?;
+        }
+        self.seen(&content);
+
+        Ok(unseen)
+    }
+
+    // previous returns the newest entry before index that starts with prefix, entries that are the
+    // same as the current line are skipped so duplicates dont have to be walked one by one
+    pub fn previous(&self, index: usize, prefix: &str, current: &str) -> Option<usize> {
+        self.entries[..index.min(self.entries.len())].iter()
+            .rposition(|entry| entry.line.starts_with(prefix) && entry.line != current)
+    }
+
+    // next returns the oldest entry after index that starts with prefix
+    pub fn next(&self, index: usize, prefix: &str, current: &str) -> Option<usize> {
+        let start = (index + 1).min(self.entries.len());
+
+        self.entries[start..].iter()
+            .position(|entry| entry.line.starts_with(prefix) && entry.line != current)
+            .map(|position| start + position)
+    }
+
+    // suggest returns the newest entry that continues what has been typed so far, entries run in
+    // the current directory win over newer entries from elsewhere
+    pub fn suggest(&self, prefix: &str, cwd: Option<&str>) -> Option<&str> {
+        if prefix.is_empty() || self.is_empty() {
+            return None;
+        }
+
+        let mut candidates = self.entries.iter()
+            .rev()
+            .filter(|entry| entry.line.len() > prefix.len() && entry.line.starts_with(prefix));
+
+        let fallback = candidates.clone().next();
+        let local = candidates.find(|entry| cwd.is_some() && entry.cwd.as_deref() == cwd);
+
+        local.or(fallback).map(|entry| entry.line.as_str())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, fs, process};
+
+    // file returns a history file of its own for every test
+    fn file(name: &str) -> String {
+        let path = env::temp_dir().join(format!("nsh-history-test-{}-{name}", process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    fn lines(path: &str) -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines().map(|record| Entry::parse(record).line).collect()
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let entry = Entry {
+            line: String::from("printf 'a\\tb\\n'\tdone\nsecond \\line"),
+            cwd: Some(String::from("/tmp/with\ttab\nand\\slash")),
+            timestamp: Some(1_700_000_000),
+            duration: Some(42),
+            status: Some(-1),
+        };
+
+        let record = entry.record();
+        assert_eq!(record.matches('\n').count(), 1);
+        assert_eq!(Entry::parse(record.trim_end_matches('\n')), entry);
+    }
+
+    #[test]
+    fn records_can_miss_fields() {
+        let entry = Entry::new("ls");
+        assert_eq!(entry.record(), ": \t\t\t\tls\n");
+        assert_eq!(Entry::parse(": \t\t\t\tls"), entry);
+
+        let parsed = Entry::parse(": 12\tnope\t\t/home\tls -l");
+        assert_eq!(parsed.timestamp, Some(12));
+        assert_eq!(parsed.duration, None);
+        assert_eq!(parsed.status, None);
+        assert_eq!(parsed.cwd.as_deref(), Some("/home"));
+        assert_eq!(parsed.line, "ls -l");
+    }
+
+    #[test]
+    fn old_lines_are_kept_as_they_are() {
+        for record in ["echo hi", ": not\tenough fields", "cd \\n"] {
+            assert_eq!(Entry::parse(record), Entry::new(record));
+        }
+    }
+
+    #[test]
+    fn delete_keeps_what_other_sessions_wrote() {
+        let path = file("delete");
+        let mut first = History::new();
+        let mut second = History::new();
+        first.load(&path, 100).unwrap();
+        second.load(&path, 100).unwrap();
+
+        for line in ["one", "two"] {
+            first.append(&path, &Entry::new(line), 100).unwrap();
+            first.add(Entry::new(line), 100);
+        }
+        second.append(&path, &Entry::new("other"), 100).unwrap();
+        first.append(&path, &Entry::new("three"), 100).unwrap();
+        first.add(Entry::new("three"), 100);
+        second.append(&path, &Entry::new("later"), 100).unwrap();
+
+        assert!(first.remove(Some(&path), 1).unwrap());
+        assert_eq!(lines(&path), ["one", "other", "three", "later"]);
+
+        // what the other session wrote last is still new to this one
+        first.update(&path, 100).unwrap();
+        assert_eq!(first.get(2).map(|entry| entry.line.as_str()), Some("later"));
+
+        assert!(!first.remove(Some(&path), 10).unwrap());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn clear_empties_the_file() {
+        let path = file("clear");
+        let mut history = History::new();
+        history.load(&path, 100).unwrap();
+        history.append(&path, &Entry::new("one"), 100).unwrap();
+
+        history.clear(Some(&path)).unwrap();
+        assert!(history.is_empty());
+        assert!(lines(&path).is_empty());
+        let _ = fs::remove_file(&path);
+    }
+}
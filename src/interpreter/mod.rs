@@ -3,20 +3,30 @@ use crate::{NshErrorType, NshError};
 use crate::escape;
 use crate::config::Config;
 use crate::lexer;
+use crate::hash::CommandHash;
+use crate::completion::{Spec, Source};
+use crate::readline::{self, ReadLine};
 
-use std::process::Command;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, ExitStatus};
 use std::env;
+use std::io;
 use lib_lexin::Token;
 
 
 pub struct Machine {
     errors: NshErrorType,
+    pub hash: CommandHash,
+    // status is the exit status of the last command
+    status: i32,
 }
 
 impl Machine {
     pub fn new() -> Machine {
         Machine {
             errors: NshErrorType::new(),
+            hash: CommandHash::new(),
+            status: 0,
         }
     }
 
@@ -80,13 +90,313 @@ impl Machine {
         }
     }
 
-    pub fn exec(&mut self, config: &mut Config, ast: &[Node], output_on: bool) -> Option<String> {
+    fn hash(&mut self, args: &[Value]) -> Result<(), NshError> {
+        let args = self.arg_values(args);
+
+        match args.first().map(String::as_str) {
+            None => {
+                let entries = self.hash.entries();
+                if entries.is_empty() {
+                    println!("hash: hash table empty");
+                    return Ok(());
+                }
+
+                println!("hits\tcommand");
+                for (_, path, hits) in entries {
+                    println!("{hits:4}\t{}", path.display());
+                }
+            },
+            Some("-r") => {
+                self.hash.clear();
+            },
+            Some("-t") => {
+                if args.len() < 2 {
+                    return Err(NshError::Hash("-t expects at least 1 name".to_string()));
+                }
+
+                for name in &args[1..] {
+                    match self.hash.get(name) {
+                        Some(path) => println!("{}", path.display()),
+                        None => return Err(NshError::Hash(format!("{name}: not found"))),
+                    }
+                }
+            },
+            Some(_) => {
+                for name in &args {
+                    if self.hash.remember(name).is_none() {
+                        return Err(NshError::Hash(format!("{name}: not found")));
+                    }
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn complete(&self, config: &mut Config, args: &[Value]) -> Result<(), NshError> {
+        let args = self.arg_values(args);
+
+        if args.is_empty() {
+            for spec in &config.completions {
+                println!("{spec}");
+            }
+            return Ok(());
+        }
+
+        let mut position: Option<usize> = None;
+        let mut source: Option<Source> = None;
+        let mut remove = false;
+        let mut commands: Vec<String> = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-p" => {
+                    let Some(pos) = args.next().and_then(|pos| pos.parse::<usize>().ok()) else {
+                        return Err(NshError::Complete("-p expects an argument position".to_string()));
+                    };
+                    position = Some(pos);
+                },
+                "-W" => {
+                    let Some(words) = args.next() else {
+                        return Err(NshError::Complete("-W expects a list of words".to_string()));
+                    };
+                    source = Some(Source::Words(words.split_whitespace().map(str::to_string).collect()));
+                },
+                "-C" => {
+                    let Some(command) = args.next() else {
+                        return Err(NshError::Complete("-C expects a command".to_string()));
+                    };
+                    source = Some(Source::Command(command));
+                },
+                "-r" => {
+                    remove = true;
+                },
+                _ => {
+                    commands.push(arg);
+                },
+            }
+        }
+
+        if commands.is_empty() {
+            return Err(NshError::Complete("expected a command name".to_string()));
+        }
+
+        if remove {
+            config.completions.retain(|spec| !commands.contains(&spec.command));
+            return Ok(());
+        }
+
+        let Some(source) = source else {
+            return Err(NshError::Complete("expected -W <Words> or -C <Command>".to_string()));
+        };
+
+        for command in commands {
+            // registering the same command and position again replaces the old spec
+            config.completions.retain(|spec| spec.command != command || spec.position != position);
+            config.completions.push(Spec {
+                command,
+                position,
+                source: source.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn print_history(rl: &ReadLine, indices: impl Iterator<Item = usize>) {
+        for index in indices {
+            if let Some(entry) = rl.history().get(index) {
+                println!("{:5}  {}", index + 1, entry.line);
+            }
+        }
+    }
+
+    fn history(&self, rl: &mut ReadLine, args: &[Value]) -> Result<(), NshError> {
+        let args = self.arg_values(args);
+        let len = rl.history().len();
+
+        match args.first().map(String::as_str) {
+            None => {
+                Machine::print_history(rl, 0..len);
+            },
+            Some("-c") => {
+                if let Err(err) = rl.clear_history() {
+                    return Err(NshError::HistorySave(err.to_string()));
+                }
+            },
+            Some("-d") => {
+                // entries are numbered from 1 like they are listed
+                let Some(number) = args.get(1).and_then(|number| number.parse::<usize>().ok()) else {
+                    return Err(NshError::HistoryBuiltin("-d expects an entry number".to_string()));
+                };
+
+                match number.checked_sub(1).map_or(Ok(false), |index| rl.delete_history(index)) {
+                    Ok(true) => {},
+                    Ok(false) => return Err(NshError::HistoryBuiltin(format!("{number}: no such entry"))),
+                    Err(err) => return Err(NshError::HistorySave(err.to_string())),
+                }
+            },
+            Some("-g") => {
+                let Some(pattern) = args.get(1) else {
+                    return Err(NshError::HistoryBuiltin("-g expects a pattern".to_string()));
+                };
+
+                let matches = (0..len)
+                    .filter(|index| rl.history().get(*index).is_some_and(|entry| entry.line.contains(pattern.as_str())))
+                    .collect::<Vec<usize>>();
+                Machine::print_history(rl, matches.into_iter());
+            },
+            Some(count) => {
+                // a number lists only the newest entries
+                let Ok(count) = count.parse::<usize>() else {
+                    return Err(NshError::HistoryBuiltin(format!("unknown argument `{count}`")));
+                };
+
+                Machine::print_history(rl, len.saturating_sub(count)..len);
+            },
+        }
+
+        Ok(())
+    }
+
+    // fc opens a history entry in the editor and runs it once it is saved, the entry is the last
+    // one, a number like `history` lists them, a negative number counting back from the last one
+    // or the last entry starting with a prefix
+    fn fc(&self, rl: &mut ReadLine, args: &[Value]) -> Result<(), NshError> {
+        let args = self.arg_values(args);
+        let history = rl.history();
+        let len = history.len();
+
+        let index = match args.first() {
+            None => len.checked_sub(1),
+            Some(arg) => match arg.parse::<isize>() {
+                Ok(number) if number < 0 => len.checked_sub(number.unsigned_abs()),
+                Ok(number) => usize::try_from(number).ok().and_then(|number| number.checked_sub(1)),
+                Err(_) => (0..len).rev().find(|index| history.get(*index).is_some_and(|entry| entry.line.starts_with(arg.as_str()))),
+            },
+        };
+
+        let Some(entry) = index.and_then(|index| history.get(index)) else {
+            return Err(NshError::Fc(String::from("no such entry in the history")));
+        };
+
+        let line = match readline::edit(&entry.line) {
+            Ok(line) => line,
+            Err(err) => return Err(NshError::Fc(err)),
+        };
+
+        // saving an empty file runs nothing
+        if !line.trim().is_empty() {
+            rl.queue(line);
+        }
+
+        Ok(())
+    }
+
+    fn bind(&self, rl: &mut ReadLine, args: &[Value]) -> Result<(), NshError> {
+        let args = self.arg_values(args);
+        let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+
+        let result = match args.as_slice() {
+            [] => {
+                for (sequence, binding) in rl.bindings() {
+                    println!("{sequence}: {binding}");
+                }
+                Ok(())
+            },
+            ["-l"] => {
+                for (action, _) in readline::ACTIONS {
+                    println!("{action}");
+                }
+                Ok(())
+            },
+            ["-r", sequence] => rl.unbind(sequence),
+            ["-c", sequence, command] => rl.bind_command(sequence, command),
+            [sequence, action] => rl.bind(sequence, action),
+            _ => Err(String::from("expected bind <Sequence> <Action>, bind -c <Sequence> <Command>, bind -r <Sequence> or bind -l")),
+        };
+
+        result.map_err(NshError::Bind)
+    }
+
+    // fail records an error from a node, a node that fails without a more specific exit status
+    // gets the status 1
+    fn fail(&mut self, err: NshError) {
+        if self.status == 0 {
+            self.status = 1;
+        }
+        self.errors.push(err);
+    }
+
+    // exit_status follows the shell convention of 128 plus the signal for killed processes
+    fn exit_status(status: ExitStatus) -> i32 {
+        status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+    }
+
+    fn spawn_status(err: &io::Error) -> i32 {
+        if err.kind() == io::ErrorKind::PermissionDenied {
+            126
+        } else {
+            127
+        }
+    }
+
+    fn command(&mut self, file: &str) -> Result<Command, NshError> {
+        let Some(path) = self.hash.resolve(file) else {
+            return Err(NshError::Exec(format!("{file}: command not found")));
+        };
+
+        let mut command = Command::new(path);
+        // keep argv[0] as the name the user typed instead of the resolved path
+        command.arg0(file);
+
+        Ok(command)
+    }
+
+    // builtin runs the builtins that only change the state of the shell or print it
+    fn builtin(&mut self, config: &mut Config, rl: &mut ReadLine, node: &Node) -> Result<(), NshError> {
+        match node {
+            Node::Hash(args) => self.hash(args),
+            Node::Complete(args) => self.complete(config, args),
+            Node::History(args) => self.history(rl, args),
+            Node::Bind(args) => self.bind(rl, args),
+            Node::Fc(args) => self.fc(rl, args),
+            Node::Set(option, value) => self.set(config, option, value),
+            _ => Ok(()),
+        }
+    }
+
+    // set changes an option, without one every option is listed
+    fn set(&self, config: &mut Config, option: &Value, value: &Value) -> Result<(), NshError> {
+        if *option == Value::default() {
+            for (option, value) in config.options() {
+                println!("{option}: {value}");
+            }
+            return Ok(());
+        }
+
+        config.set(&self.value(option), &self.value(value)).map_err(NshError::Set)
+    }
+
+    pub fn exec(&mut self, config: &mut Config, rl: &mut ReadLine, ast: &[Node], output_on: bool) -> Option<String> {
         let mut output = String::new();
         for node in ast {
+            self.status = 0;
+
             match node {
                 Node::Exec {file, args} => {
+                    let mut command = match self.command(file) {
+                        Ok(command) => command,
+                        Err(err) => {
+                            self.status = 127;
+                            self.fail(err);
+                            return self.ret_exec(output_on, output);
+                        },
+                    };
+
                     if output_on {
-                        let process = Command::new(file)
+                        let process = command
                             .args(self.arg_values(args))
                             // here we use output instead of spawn, output will run the program and
                             // collect stdout instead of directly piping the process stdout to the user
@@ -94,42 +404,51 @@ impl Machine {
                             .output();
 
                         if let Err(err) = &process {
-                            self.errors.push(NshError::Exec(err.to_string()));
+                            self.status = Machine::spawn_status(err);
+                            self.fail(NshError::Exec(err.to_string()));
                             return self.ret_exec(output_on, output);
                         } else if let Ok(proc) = &process {
+                            self.status = Machine::exit_status(proc.status);
+
                             let stdout = String::from_utf8(proc.stdout.clone());
                             match stdout {
                                 Ok(value) => {
                                     output = output + &value;
                                 },
                                 Err(err) => {
-                                    self.errors.push(NshError::Utf8(err.to_string()));
+                                    self.fail(NshError::Utf8(err.to_string()));
                                     return self.ret_exec(output_on, output);
                                 },
                             }
                         }
                     } else {
-                        let process = Command::new(file)
+                        let process = command
                             .args(self.arg_values(args))
                             // .envs(&self.env)
                             .spawn();
 
                         if let Err(err) = &process {
-                            self.errors.push(NshError::Exec(err.to_string()));
+                            self.status = Machine::spawn_status(err);
+                            self.fail(NshError::Exec(err.to_string()));
                             return self.ret_exec(output_on, output);
                         }
 
                         // Calling process::Child::wait() halts execution until the child process has
                         // successfully exited
-                        if let Err(err) = process.unwrap().wait() {
-                            self.errors.push(NshError::ExecWait(err.to_string()));
-                            return self.ret_exec(output_on, output);
+                        match process.unwrap().wait() {
+                            Ok(status) => {
+                                self.status = Machine::exit_status(status);
+                            },
+                            Err(err) => {
+                                self.fail(NshError::ExecWait(err.to_string()));
+                                return self.ret_exec(output_on, output);
+                            },
                         }
                     }
                 },
                 Node::Cd(dir) => {
                     if let Err(err) = self.cd(dir) {
-                        self.errors.push(err);
+                        self.fail(err);
                         return self.ret_exec(output_on, output);
                     }
                 },
@@ -146,7 +465,7 @@ impl Machine {
                         let replacement_signature = lexer::tokenize(&self.value(replacement));
 
                         if original_signature.is_err() || replacement_signature.is_err() {
-                            self.errors.push(NshError::Lexical("failed to tokenize".to_string()));
+                            self.fail(NshError::Lexical("failed to tokenize".to_string()));
                             return self.ret_exec(output_on, output);
                         }
 
@@ -155,7 +474,7 @@ impl Machine {
 
                         let original_len = original_signature.len();
                         if original_len != 1 {
-                            self.errors.push(NshError::Alias("Alias can only accept 1 token as the match".to_string()));
+                            self.fail(NshError::Alias("Alias can only accept 1 token as the match".to_string()));
                             return self.ret_exec(output_on, output);
                         }
 
@@ -163,8 +482,19 @@ impl Machine {
                         config.alias.push((original_signature[0].clone(), replacement_signature.unwrap()));
                     }
                 },
+                Node::Hash(_) | Node::Complete(_) | Node::History(_) | Node::Bind(_) | Node::Fc(_) | Node::Set(_, _) => {
+                    if let Err(err) = self.builtin(config, rl, node) {
+                        self.fail(err);
+                        return self.ret_exec(output_on, output);
+                    }
+                },
                 Node::SetEnv(env, value) => {
                     env::set_var(env, self.value(value));
+
+                    // a new $PATH makes every hashed location stale
+                    if env == "PATH" {
+                        self.hash.clear();
+                    }
                 },
                 Node::Pipe(_, _) => {
                     // TODO: implement pipe
@@ -176,6 +506,15 @@ impl Machine {
         return self.ret_exec(output_on, output);
     }
 
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    // set_status puts back a status that running something on the side overwrote
+    pub fn set_status(&mut self, status: i32) {
+        self.status = status;
+    }
+
     pub fn errors(&self) -> &NshErrorType {
         &self.errors
     }
@@ -0,0 +1,185 @@
+use crate::history::History;
+
+
+// words splits a history entry on spaces outside of strings, the quotes are kept so a word can be
+// put back into a line as it was typed
+fn words(line: &str) -> Vec<&str> {
+    let mut words: Vec<&str> = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut quoted = false;
+
+    for (idx, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ' ' | '\t' if !quoted => {
+                if let Some(start) = start.take() {
+                    words.push(&line[start..idx]);
+                }
+                continue;
+            },
+            _ => {},
+        }
+
+        start.get_or_insert(idx);
+    }
+
+    if let Some(start) = start {
+        words.push(&line[start..]);
+    }
+
+    words
+}
+
+fn previous(history: &History) -> Result<&str, String> {
+    match history.len().checked_sub(1).and_then(|index| history.get(index)) {
+        Some(entry) => Ok(&entry.line),
+        None => Err(String::from("!!: event not found")),
+    }
+}
+
+// event finds the entry a designator after `!` refers to and returns it with the length of the
+// designator, `!!`, `!-n`, `!n` and `!prefix` name a whole entry while `!$` and `!*` name words of
+// the previous one
+fn event(designator: &str, history: &History) -> Result<(String, usize), String> {
+    if designator.starts_with('!') {
+        return Ok((previous(history)?.to_string(), 1));
+    } else if designator.starts_with('$') {
+        let line = previous(history)?;
+        return Ok((words(line).last().unwrap_or(&"").to_string(), 1));
+    } else if designator.starts_with('*') {
+        let line = previous(history)?;
+        return Ok((words(line).get(1..).unwrap_or_default().join(" "), 1));
+    }
+
+    let end = designator.find(|character: char| character.is_whitespace() || matches!(character, '"' | '&' | '|' | ';'))
+        .unwrap_or(designator.len());
+    let name = &designator[..end];
+
+    let index = if let Some(offset) = name.strip_prefix('-').and_then(|offset| offset.parse::<usize>().ok()) {
+        history.len().checked_sub(offset)
+    } else if let Ok(number) = name.parse::<usize>() {
+        // entries are numbered from 1 like the `history` builtin lists them
+        number.checked_sub(1).filter(|index| *index < history.len())
+    } else {
+        (0..history.len()).rev().find(|index| history.get(*index).is_some_and(|entry| entry.line.starts_with(name)))
+    };
+
+    match index.and_then(|index| history.get(index)) {
+        Some(entry) => Ok((entry.line.clone(), end)),
+        None => Err(format!("!{name}: event not found")),
+    }
+}
+
+// substitute handles the quick substitution `^old^new^` which reruns the previous entry with the
+// first occurrence of old replaced
+fn substitute(line: &str, history: &History) -> Result<String, String> {
+    let mut parts = line[1..].splitn(3, '^');
+    let old = parts.next().unwrap_or_default();
+    let new = parts.next().unwrap_or_default();
+    let rest = parts.next().unwrap_or_default();
+
+    let previous = previous(history)?;
+    if old.is_empty() || !previous.contains(old) {
+        return Err(format!("^{old}^{new}: substitution failed"));
+    }
+
+    Ok(previous.replacen(old, new, 1) + rest)
+}
+
+// expand_history replaces the history designators in a line before it is tokenized, it returns
+// None when the line has none so the caller knows whether to show the expanded line
+pub fn expand_history(line: &str, history: &History) -> Result<Option<String>, String> {
+    if line.starts_with('^') {
+        return substitute(line, history).map(Some);
+    }
+
+    let mut expanded = String::new();
+    let mut changed = false;
+    let mut rest = line;
+
+    while let Some(position) = rest.find('!') {
+        let designator = &rest[position + 1..];
+
+        // like other shells a `!` before a space, `=` or the end of the line is left alone and
+        // a backslash keeps it from being expanded
+        let literal = designator.is_empty()
+            || designator.starts_with(|character: char| character.is_whitespace() || matches!(character, '=' | '"' | '('))
+            || rest[..position].ends_with('\\');
+
+        expanded.push_str(&rest[..=position]);
+        if literal {
+            rest = designator;
+            continue;
+        }
+
+        expanded.pop();
+        let (replacement, length) = event(designator, history)?;
+        expanded.push_str(&replacement);
+
+        rest = &designator[length..];
+        changed = true;
+    }
+
+    expanded.push_str(rest);
+    Ok(changed.then_some(expanded))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::history::Entry;
+
+    fn history(lines: &[&str]) -> History {
+        let mut history = History::new();
+        for line in lines {
+            history.add(Entry::new(line), 100);
+        }
+        history
+    }
+
+    fn expand(line: &str, lines: &[&str]) -> Result<Option<String>, String> {
+        expand_history(line, &history(lines))
+    }
+
+    #[test]
+    fn bang_bang_is_the_previous_line() {
+        assert_eq!(expand("sudo !!", &["ls", "cat file"]), Ok(Some(String::from("sudo cat file"))));
+        assert_eq!(expand("!! | wc -l", &["ls"]), Ok(Some(String::from("ls | wc -l"))));
+        assert!(expand("!!", &[]).is_err());
+    }
+
+    #[test]
+    fn bang_dollar_is_the_last_word() {
+        let lines = ["touch \"a file\" b", "mkdir \"new dir\""];
+        assert_eq!(expand("cd !$", &lines), Ok(Some(String::from("cd \"new dir\""))));
+        assert_eq!(expand("ls !*", &lines[..1]), Ok(Some(String::from("ls \"a file\" b"))));
+    }
+
+    #[test]
+    fn entries_are_found_by_number_and_prefix() {
+        let lines = ["echo one", "ls", "echo two"];
+        assert_eq!(expand("!1", &lines), Ok(Some(String::from("echo one"))));
+        assert_eq!(expand("!-2", &lines), Ok(Some(String::from("ls"))));
+        assert_eq!(expand("!ec && !l", &lines), Ok(Some(String::from("echo two && ls"))));
+        assert!(expand("!9", &lines).is_err());
+        assert!(expand("!nope", &lines).is_err());
+    }
+
+    #[test]
+    fn caret_substitutes_in_the_previous_line() {
+        let lines = ["git stauts -s"];
+        assert_eq!(expand("^stauts^status", &lines), Ok(Some(String::from("git status -s"))));
+        assert_eq!(expand("^-s^^ -v", &lines), Ok(Some(String::from("git stauts  -v"))));
+        assert!(expand("^missing^x", &lines).is_err());
+    }
+
+    #[test]
+    fn lone_and_escaped_bangs_are_kept() {
+        let lines = ["ls"];
+        assert_eq!(expand("echo hi!", &lines), Ok(None));
+        assert_eq!(expand("echo ! x != y", &lines), Ok(None));
+        assert_eq!(expand("echo \\!!", &lines), Ok(None));
+    }
+}
@@ -1,12 +1,91 @@
+mod history;
+
 use lib_lexin::{Lexer, Section, Token};
 
+pub use history::expand_history;
 
-pub fn tokenize(source: &str) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
-    let mut lexer = Lexer::new(
-        &[
-            "cd",
-            "alias",
-        ],
+
+// KEYWORDS are the builtins that the parser handles itself instead of executing
+pub const KEYWORDS: [&str; 8] = [
+    "cd",
+    "alias",
+    "hash",
+    "complete",
+    "set",
+    "history",
+    "bind",
+    "fc",
+];
+
+
+// lines splits the source on every newline that isnt inside of a string, every line comes with
+// where it starts in the source
+fn lines(source: &str) -> Vec<(usize, &str)> {
+    let mut lines: Vec<(usize, &str)> = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (idx, character) in source.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match character {
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '\n' if !quoted => {
+                lines.push((start, &source[start..idx]));
+                start = idx + 1;
+            },
+            _ => {},
+        }
+    }
+
+    if start < source.len() {
+        lines.push((start, &source[start..]));
+    }
+
+    lines
+}
+
+// incomplete tells if the source ends inside of a string, the line editor then starts a new line
+// instead of running it
+pub fn incomplete(source: &str) -> bool {
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for character in source.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match character {
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ => {},
+        }
+    }
+
+    quoted
+}
+
+fn relocate(token: Token, row: usize) -> Token {
+    match token {
+        Token::Keyword(keyword, loc) => Token::Keyword(keyword, (loc.0 + row, loc.1)),
+        Token::Section(name, value, loc) => Token::Section(name, value, (loc.0 + row, loc.1)),
+        Token::Integer(integer, loc) => Token::Integer(integer, (loc.0 + row, loc.1)),
+        Token::Float(float, loc) => Token::Float(float, (loc.0 + row, loc.1)),
+        Token::Symbol(symbol, name, loc) => Token::Symbol(symbol, name, (loc.0 + row, loc.1)),
+        Token::Ident(ident, loc) => Token::Ident(ident, (loc.0 + row, loc.1)),
+    }
+}
+
+fn lexer() -> Lexer<'static> {
+    Lexer::new(
+        &KEYWORDS,
         &[
             Section::new("string", "\"", "\""),
             Section::new("env", "$", "$"),
@@ -17,10 +96,199 @@ pub fn tokenize(source: &str) -> Result<Vec<Token>, Box<dyn std::error::Error>>
             ('|', "Or"),
             ('\n', "NewLine"),
         ],
-    );
-    lexer.load_str(source);
+    )
+}
+
+// Span is a token along with the bytes of the source it was read from
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub token: Token,
+}
+
+// unescape takes the backslashes out of the inside of a section the way lib_lexin does
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut characters = text.chars();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => unescaped.extend(characters.next()),
+            _ => unescaped.push(character),
+        }
+    }
+
+    unescaped
+}
+
+// source puts the text of the line back into a token that was lexed from the ascii copy of it, a
+// number is an argument like any other word so it keeps how it was written
+fn source(token: Token, text: &str) -> Token {
+    match token {
+        Token::Section(name, _, loc) => Token::Section(name, unescape(&text[1..text.len() - 1]), loc),
+        Token::Ident(_, loc) | Token::Integer(_, loc) | Token::Float(_, loc) => Token::Ident(text.to_string(), loc),
+        token => token,
+    }
+}
+
+// ascii is the line as lib_lexin can read it, it only reads ascii and its locations skip a byte for
+// every escape in a string so everything else and the escapes are replaced by as many placeholder
+// bytes, the locations of the tokens then match up with the line and the text is taken from it
+fn ascii(line: &str) -> String {
+    let mut ascii = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for character in line.chars() {
+        let hidden = if escaped {
+            escaped = false;
+            character != '\n'
+        } else if quoted && character == '\\' {
+            escaped = true;
+            true
+        } else {
+            quoted ^= character == '"';
+            !character.is_ascii()
+        };
 
-    return lexer.tokenize();
+        if hidden {
+            ascii.push_str(&"_".repeat(character.len_utf8()));
+        } else {
+            ascii.push(character);
+        }
+    }
+
+    ascii
+}
+
+// lex tokenizes a single line and returns the tokens with where they are in the line, along with
+// what is left at the end that wasnt finished like a string without its closing quote
+fn lex(line: &str) -> Result<(Vec<Span>, Option<Span>), Box<dyn std::error::Error>> {
+    let mut lexer = lexer();
+    lexer.load_str(&format!("{}\n", ascii(line)));
+
+    let mut spans: Vec<Span> = Vec::new();
+    let mut end = 0;
+
+    for token in lexer.tokenize()? {
+        // the location is the row and column of the last character of the token, the rows only
+        // go past the first one for strings with newlines in them
+        let (row, column) = token.loc();
+        let row_start = match row {
+            0 | 1 => 0,
+            _ => line.match_indices('\n').nth(row - 2).map_or(0, |(idx, _)| idx + 1),
+        };
+        let last = (row_start + column).saturating_sub(1).min(line.len().saturating_sub(1));
+
+        // everything but whitespace ends up in a token so the token starts after the whitespace
+        let start = line[end..].find(|character: char| !character.is_whitespace()).map_or(end, |idx| end + idx);
+        if start > last {
+            continue;
+        }
+
+        spans.push(Span { start, end: last + 1, token: source(token, &line[start..=last]) });
+        end = last + 1;
+    }
+
+    let rest = line[end..].trim_start();
+    if rest.is_empty() {
+        return Ok((spans, None));
+    }
+
+    let start = line.len() - rest.len();
+    let loc = (1, line.len());
+
+    let token = match rest.chars().next() {
+        Some('"') => Token::Section(String::from("string"), unescape(&rest[1..]), loc),
+        Some('$') => Token::Section(String::from("env"), rest[1..].to_string(), loc),
+        _ => Token::Ident(rest.to_string(), loc),
+    };
+    Ok((spans, Some(Span { start, end: line.len(), token })))
 }
 
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut row = 0;
+
+    // lib_lexin drops newlines instead of producing the NewLine symbol, so every line is tokenized
+    // on its own and the separators are added back in between, a string that isnt closed is
+    // dropped like lib_lexin does
+    for (_, line) in lines(source) {
+        let (spans, _) = lex(line)?;
+
+        for span in spans {
+            tokens.push(relocate(span.token, row));
+        }
+
+        row += line.matches('\n').count() + 1;
+        tokens.push(Token::Symbol('\n', String::from("NewLine"), (row, line.len() + 1)));
+    }
+
+    Ok(tokens)
+}
 
+// spans tokenizes the source like tokenize but keeps input that isnt finished yet like a string
+// without its closing quote as a token too, it is used to highlight the line while it is typed
+pub fn spans(source: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+
+    for (offset, line) in lines(source) {
+        let (tokens, rest) = lex(line).unwrap_or_default();
+
+        for span in tokens.into_iter().chain(rest) {
+            spans.push(Span { start: offset + span.start, end: offset + span.end, token: span.token });
+        }
+    }
+
+    spans
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(source: &str) -> Vec<String> {
+        tokenize(source).unwrap().iter().map(Token::as_string).collect()
+    }
+
+    #[test]
+    fn tokenize_keeps_non_ascii_words() {
+        assert_eq!(texts("echo héllo wörld\n"), ["echo", "héllo", "wörld", "NewLine"]);
+    }
+
+    #[test]
+    fn tokenize_keeps_numbers_as_written() {
+        assert_eq!(texts("echo 007 1e3 1.50 42\n"), ["echo", "007", "1e3", "1.50", "42", "NewLine"]);
+        assert!(tokenize("echo 42\n").unwrap()[1].is_ident().is_ok());
+    }
+
+    #[test]
+    fn tokenize_keeps_non_ascii_strings() {
+        let tokens = tokenize("echo \"日本 \\\"語\\\"\"\n").unwrap();
+        assert_eq!(tokens[1].as_string(), "日本 \"語\"");
+        assert!(tokens[2].is_symbol("NewLine").is_ok());
+    }
+
+    #[test]
+    fn spans_after_escapes_line_up() {
+        let source = "echo \"a\\\"b\" c";
+        let texts = spans(source).iter().map(|span| &source[span.start..span.end]).collect::<Vec<&str>>();
+        assert_eq!(texts, ["echo", "\"a\\\"b\"", "c"]);
+    }
+
+    #[test]
+    fn spans_cover_the_real_text() {
+        let source = "echo héllo \"ünfinished";
+        let spans = spans(source);
+
+        let texts = spans.iter().map(|span| &source[span.start..span.end]).collect::<Vec<&str>>();
+        assert_eq!(texts, ["echo", "héllo", "\"ünfinished"]);
+        assert_eq!(spans[2].token.as_string(), "ünfinished");
+    }
+
+    #[test]
+    fn newlines_separate_commands() {
+        assert_eq!(texts("cd a\necho \"b\nc\"\n"), ["cd", "a", "NewLine", "echo", "b\nc", "NewLine"]);
+    }
+}
@@ -8,10 +8,14 @@ mod escape;
 mod completion;
 mod readline;
 mod signals;
+mod hash;
+mod history;
+mod prompt;
 
 use std::fs;
 use std::env;
 use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use argin::Argin;
 
@@ -26,8 +30,16 @@ pub enum NshError {
     Prompt(String),
     Parser(String),
     History(String),
+    HistorySave(String),
+    HistoryBuiltin(String),
+    Expansion(String),
+    Bind(String),
+    Fc(String),
     Utf8(String),
     Alias(String),
+    Hash(String),
+    Complete(String),
+    Set(String),
 }
 
 pub struct NshErrorType {
@@ -84,12 +96,36 @@ impl NshErrorType {
                 NshError::History(err) => {
                     println!("[ERROR]: Failed to load history -> `{err}`");
                 },
+                NshError::HistorySave(err) => {
+                    println!("[ERROR]: Failed to save history -> `{err}`");
+                },
+                NshError::HistoryBuiltin(err) => {
+                    println!("[ERROR]: History failed with message -> `{err}`");
+                },
+                NshError::Expansion(err) => {
+                    println!("[ERROR]: History expansion failed -> `{err}`");
+                },
+                NshError::Bind(err) => {
+                    println!("[ERROR]: Bind failed with message -> `{err}`");
+                },
+                NshError::Fc(err) => {
+                    println!("[ERROR]: Fc failed with message -> `{err}`");
+                },
                 NshError::Utf8(err) => {
                     println!("[ERROR]: Failed to parse utf8 -> `{err}`");
                 },
                 NshError::Alias(err) => {
                     println!("[ERROR]: Alias failed with message -> `{err}`");
                 },
+                NshError::Hash(err) => {
+                    println!("[ERROR]: Hash failed with message -> `{err}`");
+                },
+                NshError::Complete(err) => {
+                    println!("[ERROR]: Complete failed with message -> `{err}`");
+                },
+                NshError::Set(err) => {
+                    println!("[ERROR]: Set failed with message -> `{err}`");
+                },
             }
         }
 
@@ -106,6 +142,7 @@ pub struct Nsh {
     errors: NshErrorType,
     vm: interpreter::Machine,
     config: config::Config,
+    rl: readline::ReadLine,
 }
 
 impl Nsh {
@@ -114,46 +151,121 @@ impl Nsh {
             errors: NshErrorType::new(),
             vm: interpreter::Machine::new(),
             config: config::Config::new(),
+            rl: readline::ReadLine::new(),
         }
     }
 
     pub fn exec_line(&mut self, buf: &str, output: bool) -> Option<String> {
         // if it matches with a alias then replace it
 
-        let tokens = lexer::tokenize(&buf);
-
-        if let Err(err) = &tokens {
-            self.errors.push(NshError::Lexical(err.to_string()));
-        }
-
-        // Prevent drop while borrowed in Ast::new()
-        let tokens = tokens.unwrap();
+        let tokens = match lexer::tokenize(buf) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                self.errors.push(NshError::Lexical(err.to_string()));
+                return None;
+            },
+        };
 
         let mut parser = parser::Ast::new(&tokens, &mut self.errors);
         let ast = parser.parse(&self.config);
 
         if output {
-            Some(self.vm.exec(&mut self.config, ast, true).unwrap())
+            Some(self.vm.exec(&mut self.config, &mut self.rl, ast, true).unwrap())
         } else {
-            self.vm.exec(&mut self.config, ast, false);
+            self.vm.exec(&mut self.config, &mut self.rl, ast, false);
             None
         }
     }
 
+    // accept runs the line that was read after history expansion, the expanded line is shown so it
+    // is clear what actually runs
+    fn accept(&mut self) {
+        match lexer::expand_history(&self.rl.buffer, self.rl.history()) {
+            Ok(Some(expanded)) => {
+                println!("{expanded}");
+                self.run(&expanded);
+            },
+            Ok(None) => {
+                self.run(&self.rl.buffer.clone());
+            },
+            Err(err) => {
+                self.errors.push(NshError::Expansion(err));
+            },
+        }
+    }
+
+    // run executes a line typed at the prompt and records it in the history along with how it went
+    fn run(&mut self, line: &str) {
+        let cwd = env::current_dir().ok().and_then(|cwd| cwd.to_str().map(str::to_string));
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).ok();
+        let start = Instant::now();
+
+        self.exec_line(&(line.to_string() + "\n"), false);
+
+        let entry = history::Entry {
+            line: line.to_string(),
+            cwd,
+            timestamp,
+            duration: u64::try_from(start.elapsed().as_millis()).ok(),
+            status: Some(self.vm.status()),
+        };
+
+        if let Err(err) = self.rl.add_history(&entry, &self.config) {
+            self.errors.push(NshError::HistorySave(err.to_string()));
+        }
+    }
+
     pub fn load_config(&mut self, file: &str) -> Result<(), Box<dyn std::error::Error>> {
         let buffer = fs::read_to_string(file)?;
         self.exec_line(&buffer, false);
         Ok(())
     }
 
-    fn prompt(&mut self) -> Result<String, Box<dyn std::error::Error>> {
-        let ps1 = env::var("PS1").unwrap_or(String::new());
-        let output = self.exec_line(&ps1, true);
-        if let Some(proc_output) = &output {
-            Ok(proc_output.clone())
-        } else {
-            Ok(String::new())
+    fn load_completions(&mut self, dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // every file in the completions directory is a script of `complete` specs
+        let mut files = fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+
+        files.sort();
+
+        for file in files {
+            if let Some(file) = file.to_str() {
+                self.load_config(file)?;
+            }
         }
+
+        Ok(())
+    }
+
+    // prompt renders $PS1, $RPS1 and $TRANSIENT_PS1 as templates, the commands in `$(...)` are run
+    // right away by nsh and the ones in `$&(...)` in the background by sh. the prompt of a line is
+    // rewritten to the transient one once it is done and left as it is while that is empty
+    fn prompt(&mut self) -> Result<prompt::Prompt, Box<dyn std::error::Error>> {
+        let ps1 = template("PS1", prompt::DEFAULT)?;
+        let rps1 = template("RPS1", "")?;
+        let transient = Some(template("TRANSIENT_PS1", "")?).filter(|transient| !transient.is_empty());
+        let placeholder = self.config.prompt_placeholder.clone();
+
+        // the commands of the prompt arent what `\?` is about, the last line typed is
+        let status = self.vm.status();
+        let prompt = prompt::Prompt::new(&ps1, &rps1, transient.as_deref(), status, &placeholder, &mut |command| {
+            self.exec_line(&format!("{command}\n"), true).unwrap_or_default()
+        });
+        self.vm.set_status(status);
+
+        Ok(prompt)
+    }
+}
+
+// template reads a prompt template from the environment, an unset one is the default
+fn template(name: &str, default: &str) -> Result<String, env::VarError> {
+    match env::var(name) {
+        Ok(template) => Ok(template),
+        Err(env::VarError::NotPresent) => Ok(default.to_string()),
+        Err(err) => Err(err),
     }
 }
 
@@ -181,7 +293,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // create new nsh instance
     let mut nsh = Nsh::new();
     nsh.vm = interpreter::Machine::new();
-    let mut rl = readline::ReadLine::new();
 
     // load history and config
     let path = match env::var("HOME") {
@@ -193,13 +304,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
     };
 
-    if let Err(err) = rl.load_history(&format!("{path}/.config/nsh/history.txt")) {
-        nsh.errors.handle_err(NshError::History(err.to_string()));
+    if let Err(err) = nsh.load_config(&format!("{path}/.config/nsh/conf.nsh")) {
+        nsh.errors.handle_err(NshError::Config(err.to_string()));
     }
 
+    // the completions directory is optional so a missing one isnt reported
+    if fs::metadata(format!("{path}/.config/nsh/completions")).is_ok() {
+        if let Err(err) = nsh.load_completions(&format!("{path}/.config/nsh/completions")) {
+            nsh.errors.handle_err(NshError::Config(err.to_string()));
+        }
+    }
 
-    if let Err(err) = nsh.load_config(&format!("{path}/.config/nsh/conf.nsh")) {
-        nsh.errors.handle_err(NshError::Config(err.to_string()));
+    // history is loaded after the config so history_size applies to it
+    if let Err(err) = nsh.rl.load_history(&format!("{path}/.config/nsh/history.txt"), nsh.config.history_size) {
+        nsh.errors.handle_err(NshError::History(err.to_string()));
     }
 
     loop {
@@ -208,11 +326,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             nsh.errors.push(NshError::Prompt(err.to_string()));
         }
 
-        let line = rl.input(&prompt.unwrap());
+        let line = nsh.rl.input(prompt.unwrap_or_default(), &mut nsh.vm.hash, &nsh.config);
 
         match line {
             Ok(_) => {
-                nsh.exec_line(&(rl.buffer.clone() + "\n"), false);
+                // a key bound to a command stops reading so the command can run, its output goes
+                // into the line and reading carries on with it
+                if let Some(command) = nsh.rl.take_command() {
+                    let output = nsh.exec_line(&(command + "\n"), true).unwrap_or_default();
+                    nsh.rl.insert_output(&output);
+                } else {
+                    nsh.accept();
+                }
+
+                // a line edited with fc runs as if it was typed and is shown first like bash does
+                while let Some(line) = nsh.rl.take_queued() {
+                    println!("{line}");
+                    nsh.run(&line);
+                }
             },
             Err(err) => {
                 nsh.errors.push(NshError::ReadStdin(err.to_string()));
@@ -2,6 +2,8 @@ use lib_lexin::Token;
 use crate::{NshError, NshErrorType};
 use crate::config::Config;
 
+use std::env;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Value {
     Str(String),
@@ -25,6 +27,18 @@ pub enum Node {
 
     Alias(Value, Value),
 
+    Hash(Vec<Value>),
+
+    Complete(Vec<Value>),
+
+    Set(Value, Value),
+
+    History(Vec<Value>),
+
+    Bind(Vec<Value>),
+
+    Fc(Vec<Value>),
+
     SetEnv(String, Value),
 
     Pipe(Box<Node>, Box<Node>),
@@ -52,16 +66,30 @@ impl<'a> Ast<'a> {
         }
     }
 
+    fn expand_home(ident: String) -> String {
+        // `~` is only expanded outside of strings, the same way completion writes it
+        if ident == "~" || ident.starts_with("~/") {
+            if let Ok(home) = env::var("HOME") {
+                return home + &ident[1..];
+            }
+        }
+
+        ident
+    }
+
     fn value(&mut self, token: &Token) -> Value {
         // identifiers in lib_lexin is anything that is unreconized by the tokenizer
         // therefore when i say ident here it just represents a value
 
         if let Ok(string) = token.is_ident() {
-            return Value::Str(string);
+            return Value::Str(Ast::expand_home(string));
         } else if let Ok(string) = token.is_section("string") {
             return Value::Str(string);
         } else if let Ok(env) = token.is_section("env") {
             return Value::Env(env);
+        } else if let Token::Keyword(keyword, _) = token {
+            // builtin names such as `hash` are still valid arguments to other commands
+            return Value::Str(keyword.clone());
         } else {
             let loc = token.loc();
             self.errors.handle_err(NshError::Parser(format!("{}:{}: expected value but got `{}`", loc.0, loc.1, token.as_string())));
@@ -111,6 +139,38 @@ impl<'a> Ast<'a> {
             }
 
             return Some(Node::Alias(self.value(&node[1]), self.value(&node[2])));
+        } else if node[0].is_keyword("hash").is_ok() {
+            let args = node[1..].iter().map(|token| self.value(token)).collect::<Vec<Value>>();
+
+            return Some(Node::Hash(args));
+        } else if node[0].is_keyword("complete").is_ok() {
+            let args = node[1..].iter().map(|token| self.value(token)).collect::<Vec<Value>>();
+
+            return Some(Node::Complete(args));
+        } else if node[0].is_keyword("history").is_ok() {
+            let args = node[1..].iter().map(|token| self.value(token)).collect::<Vec<Value>>();
+
+            return Some(Node::History(args));
+        } else if node[0].is_keyword("bind").is_ok() {
+            let args = node[1..].iter().map(|token| self.value(token)).collect::<Vec<Value>>();
+
+            return Some(Node::Bind(args));
+        } else if node[0].is_keyword("fc").is_ok() {
+            let args = node[1..].iter().map(|token| self.value(token)).collect::<Vec<Value>>();
+
+            return Some(Node::Fc(args));
+        } else if node[0].is_keyword("set").is_ok() {
+            if node.len() == 1 {
+                // empty set simply prints all the options
+                return Some(Node::Set(Value::default(), Value::default()));
+            } else if node.len() < 3 {
+                let loc = node[0].loc();
+                self.errors.handle_err(NshError::Parser(format!("{}:{}: set expects 2 arguments", loc.0, loc.1)));
+                println!("[SYNTAX]: set <Option> <Value>");
+                return None;
+            }
+
+            return Some(Node::Set(self.value(&node[1]), self.value(&node[2])));
         } else if let Ok(env) = node[0].is_section("env") {
             let loc = node[0].loc();
 
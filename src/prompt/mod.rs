@@ -0,0 +1,403 @@
+use std::env;
+use std::mem;
+use std::thread;
+use std::process::{Command, Stdio};
+use std::os::unix::process::CommandExt;
+use std::sync::mpsc::{self, Receiver};
+
+use nix::libc;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::{self, Pid, Uid, User};
+
+use crate::signals;
+
+
+// DEFAULT is the prompt when $PS1 isnt set
+pub const DEFAULT: &str = "\\w \\$ ";
+
+// Prompt is everything shown around the line being read, right is aligned to the right end of the
+// first row and transient replaces the prompt once the line is done when it is set
+#[derive(Default)]
+pub struct Prompt {
+    pub left: String,
+    pub right: String,
+    pub transient: Option<String>,
+    // the parts are the prompts as they were rendered, they are joined again whenever one of the
+    // segments is done
+    left_parts: Vec<Part>,
+    right_parts: Vec<Part>,
+    transient_parts: Vec<Part>,
+    segments: Vec<Segment>,
+    placeholder: String,
+}
+
+impl Prompt {
+    // new renders the templates of the prompt, the segments in them start running in the
+    // background and the placeholder is shown in their place until they are done
+    pub fn new(left: &str, right: &str, transient: Option<&str>, status: i32, placeholder: &str, run: &mut dyn FnMut(&str) -> String) -> Prompt {
+        let mut segments: Vec<Segment> = Vec::new();
+
+        let mut prompt = Prompt {
+            left: String::new(),
+            right: String::new(),
+            transient: transient.map(|_| String::new()),
+            left_parts: render(left, status, run, &mut segments),
+            right_parts: render(right, status, run, &mut segments),
+            transient_parts: transient.map(|transient| render(transient, status, run, &mut segments)).unwrap_or_default(),
+            segments,
+            placeholder: placeholder.to_string(),
+        };
+
+        prompt.join();
+        prompt
+    }
+
+    // update takes in the segments that are done since the last time and tells if the prompt
+    // changed because of them
+    pub fn update(&mut self) -> bool {
+        let mut changed = false;
+        for segment in &mut self.segments {
+            changed |= segment.poll();
+        }
+
+        if changed {
+            self.join();
+        }
+        changed
+    }
+
+    // cancel stops the segments that are still running, the line they were for is done
+    pub fn cancel(&mut self) {
+        for segment in &mut self.segments {
+            segment.cancel();
+        }
+    }
+
+    fn join(&mut self) {
+        let join = |parts: &[Part]| parts.iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                Part::Segment(idx) => self.segments[*idx].value.as_deref().unwrap_or(&self.placeholder),
+            })
+            .collect::<String>();
+
+        let (left, right, transient) = (join(&self.left_parts), join(&self.right_parts), join(&self.transient_parts));
+
+        self.left = left;
+        self.right = right;
+        if self.transient.is_some() {
+            self.transient = Some(transient);
+        }
+    }
+}
+
+impl Drop for Prompt {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// Part is either text of a rendered prompt or where the output of a segment goes
+enum Part {
+    Text(String),
+    Segment(usize),
+}
+
+// Segment is a command of the prompt that runs in the background while the line is already read,
+// value is set once it is done
+struct Segment {
+    // group is the process group of the command so whatever it started is stopped along with it
+    group: Option<Pid>,
+    result: Option<Receiver<String>>,
+    value: Option<String>,
+}
+
+impl Segment {
+    // spawn starts the command with sh since the shell itself is busy reading the line, the
+    // thread waiting for it wakes up the line editor once it is done
+    fn spawn(command: &str) -> Segment {
+        let child = Command::new("sh")
+            .args(["-c", command])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn();
+
+        // a command that cant be started is done right away without any output
+        let Ok(child) = child else {
+            return Segment { group: None, result: None, value: Some(String::new()) };
+        };
+
+        let group = i32::try_from(child.id()).ok().map(Pid::from_raw);
+        let (sender, receiver) = mpsc::channel();
+        let reader = signals::thread();
+
+        thread::spawn(move || {
+            let output = child.wait_with_output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim_end_matches('\n').to_string())
+                .unwrap_or_default();
+
+            // nobody is waiting for a cancelled segment anymore
+            if sender.send(output).is_ok() {
+                signals::wake(reader);
+            }
+        });
+
+        Segment { group, result: Some(receiver), value: None }
+    }
+
+    // poll takes the output of the command when it is done and tells if that happened just now
+    fn poll(&mut self) -> bool {
+        let Some(value) = self.result.as_ref().and_then(|result| result.try_recv().ok()) else {
+            return false;
+        };
+
+        self.value = Some(value);
+        self.result = None;
+        true
+    }
+
+    fn cancel(&mut self) {
+        if self.poll() || self.result.take().is_none() {
+            return;
+        }
+
+        if let Some(group) = self.group {
+            let _ = signal::killpg(group, Signal::SIGKILL);
+        }
+    }
+}
+
+// COLOURS are the names that can be used in `\{...}`, they are joined with dots like `green.bold`
+const COLOURS: [(&str, &str); 17] = [
+    ("black", "30"),
+    ("red", "31"),
+    ("green", "32"),
+    ("yellow", "33"),
+    ("blue", "34"),
+    ("magenta", "35"),
+    ("cyan", "36"),
+    ("white", "37"),
+    ("default", "39"),
+    ("reset", "0"),
+    ("bold", "1"),
+    ("dim", "2"),
+    ("italic", "3"),
+    ("underlined", "4"),
+    ("blink", "5"),
+    ("reverse", "7"),
+    ("hidden", "8"),
+];
+
+fn user() -> String {
+    if let Ok(user) = env::var("USER") {
+        return user;
+    }
+
+    User::from_uid(Uid::current()).ok().flatten().map_or_else(|| String::from("?"), |user| user.name)
+}
+
+fn host() -> String {
+    unistd::gethostname().ok()
+        .and_then(|host| host.into_string().ok())
+        .unwrap_or_else(|| String::from("?"))
+}
+
+// directory returns the working directory with the home directory written as `~`
+fn directory() -> String {
+    let cwd = env::current_dir().map(|cwd| cwd.to_string_lossy().to_string()).unwrap_or_default();
+
+    match env::var("HOME") {
+        Ok(home) if !home.is_empty() && (cwd == home || cwd.starts_with(&format!("{home}/"))) => format!("~{}", &cwd[home.len()..]),
+        _ => cwd,
+    }
+}
+
+fn basename() -> String {
+    let directory = directory();
+    if directory == "/" || directory == "~" {
+        return directory;
+    }
+
+    directory.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+// time returns the local time as HH:MM:SS
+fn time() -> String {
+    // localtime_r only writes to the tm it is given
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        libc::localtime_r(std::ptr::from_ref(&now), std::ptr::from_mut(&mut tm));
+        tm
+    };
+
+    format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+// colour turns colour names like `green.bold` into the escape sequence that starts them, numbers
+// are one of the 256 colours
+fn colour(names: &str) -> Option<String> {
+    let codes = names.split('.')
+        .map(|name| match COLOURS.iter().find(|(colour, _)| *colour == name) {
+            Some((_, code)) => Some(code.to_string()),
+            None => name.parse::<u8>().ok().map(|number| format!("38;5;{number}")),
+        })
+        .collect::<Option<Vec<String>>>()?;
+
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
+
+// substitution returns the command in `$(...)` at the start of the template and how long it is,
+// parentheses inside of it are counted so they can be nested
+fn substitution(template: &str) -> Option<(&str, usize)> {
+    let mut depth = 0;
+
+    for (idx, character) in template.char_indices().skip(1) {
+        match character {
+            '(' => depth += 1,
+            ')' if depth == 1 => return Some((&template[2..idx], idx + 1)),
+            ')' => depth -= 1,
+            _ => {},
+        }
+    }
+
+    None
+}
+
+// render expands the escapes of a prompt template like in bash, `$(...)` is replaced by the output
+// of the command which run is asked for and `$&(...)` starts a segment that runs in the background,
+// everything it doesnt know is left as it is. the segments are POSIX sh and not nsh
+//
+// there is no job control so `\j` is always 0
+fn render(template: &str, status: i32, run: &mut dyn FnMut(&str) -> String, segments: &mut Vec<Segment>) -> Vec<Part> {
+    let mut parts: Vec<Part> = Vec::new();
+    let mut prompt = String::new();
+    let mut idx = 0;
+
+    while idx < template.len() {
+        let rest = &template[idx..];
+
+        if rest.starts_with("$&(") {
+            if let Some((command, len)) = substitution(&rest[1..]) {
+                parts.push(Part::Text(mem::take(&mut prompt)));
+                parts.push(Part::Segment(segments.len()));
+                segments.push(Segment::spawn(command));
+                idx += len + 1;
+                continue;
+            }
+        }
+
+        if rest.starts_with("$(") {
+            if let Some((command, len)) = substitution(rest) {
+                prompt.push_str(run(command).trim_end_matches('\n'));
+                idx += len;
+                continue;
+            }
+        }
+
+        let mut characters = rest.chars();
+        let character = characters.next().unwrap_or_default();
+        if character != '\\' {
+            prompt.push(character);
+            idx += character.len_utf8();
+            continue;
+        }
+
+        let Some(escape) = characters.next() else {
+            prompt.push('\\');
+            break;
+        };
+        idx += 1 + escape.len_utf8();
+
+        match escape {
+            'u' => prompt.push_str(&user()),
+            'h' => prompt.push_str(host().split('.').next().unwrap_or_default()),
+            'H' => prompt.push_str(&host()),
+            'w' => prompt.push_str(&directory()),
+            'W' => prompt.push_str(&basename()),
+            't' => prompt.push_str(&time()),
+            '$' => prompt.push(if Uid::effective().is_root() { '#' } else { '$' }),
+            'j' => prompt.push('0'),
+            '?' => prompt.push_str(&status.to_string()),
+            'n' => prompt.push('\n'),
+            'e' => prompt.push('\x1b'),
+            '\\' => prompt.push('\\'),
+            // bash needs the invisible parts marked, the width is worked out without them here
+            '[' | ']' => {},
+            '{' => {
+                let colour = rest[2..].find('}').and_then(|end| Some((colour(&rest[2..2 + end])?, end)));
+                match colour {
+                    Some((colour, end)) => {
+                        prompt.push_str(&colour);
+                        idx += end + 1;
+                    },
+                    None => prompt.push_str("\\{"),
+                }
+            },
+            _ => {
+                prompt.push('\\');
+                prompt.push(escape);
+            },
+        }
+    }
+
+    parts.push(Part::Text(prompt));
+    parts
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // text renders a template with the commands it runs written down, segments show as `<n>`
+    fn text(template: &str, status: i32, commands: &mut Vec<String>) -> String {
+        let mut segments: Vec<Segment> = Vec::new();
+        let parts = render(template, status, &mut |command| {
+            commands.push(command.to_string());
+            String::from("out\n")
+        }, &mut segments);
+
+        for segment in &mut segments {
+            segment.cancel();
+        }
+
+        parts.iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Segment(idx) => format!("<{idx}>"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn escapes_are_expanded() {
+        let dollar = if Uid::effective().is_root() { "#" } else { "$" };
+        assert_eq!(text("[\\?]\\n\\\\ \\$ ", 127, &mut Vec::new()), format!("[127]\n\\ {dollar} "));
+        assert_eq!(text("\\[\\e[1m\\]x\\j", 0, &mut Vec::new()), "\x1b[1mx0");
+        assert_eq!(text("\\q \\", 0, &mut Vec::new()), "\\q \\");
+    }
+
+    #[test]
+    fn colours_are_named_or_numbered() {
+        assert_eq!(text("\\{green.bold}a\\{reset}", 0, &mut Vec::new()), "\x1b[32;1ma\x1b[0m");
+        assert_eq!(text("\\{196}", 0, &mut Vec::new()), "\x1b[38;5;196m");
+        assert_eq!(text("\\{nope}\\{red", 0, &mut Vec::new()), "\\{nope}\\{red");
+    }
+
+    #[test]
+    fn substitutions_can_be_nested() {
+        let mut commands = Vec::new();
+        assert_eq!(text("a $(echo $(date) (x)) b $(oops", 0, &mut commands), "a out b $(oops");
+        assert_eq!(commands, ["echo $(date) (x)"]);
+    }
+
+    #[test]
+    fn background_segments_are_kept_apart() {
+        let mut commands = Vec::new();
+        assert_eq!(text("$&(sleep 5)|$(now)|$&(true)", 0, &mut commands), "<0>|out|<1>");
+        assert_eq!(commands, ["now"]);
+    }
+}
@@ -0,0 +1,101 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+
+// how many kills are remembered for Ctrl-Y and Alt-Y
+const KILL_RING_SIZE: usize = 32;
+
+
+// is_word is what Alt-B, Alt-F and Alt-D treat as a word, Ctrl-W stops at whitespace only
+pub fn is_word(character: char) -> bool {
+    character.is_alphanumeric() || character == '_'
+}
+
+pub fn is_not_space(character: char) -> bool {
+    !character.is_whitespace()
+}
+
+// word_left returns where the word before the cursor starts, anything between the cursor and the
+// word is skipped first, graphemes are classified by their first character
+pub fn word_left(buffer: &str, cursor: usize, is_word: fn(char) -> bool) -> usize {
+    let classify = |grapheme: &str| grapheme.chars().next().is_some_and(is_word);
+    let mut graphemes = buffer[..cursor].grapheme_indices(true).rev().skip_while(|(_, grapheme)| !classify(grapheme));
+    let mut start = graphemes.next().map_or(0, |(idx, _)| idx);
+
+    for (idx, grapheme) in graphemes {
+        if !classify(grapheme) {
+            break;
+        }
+        start = idx;
+    }
+
+    start
+}
+
+// word_right returns where the word after the cursor ends
+pub fn word_right(buffer: &str, cursor: usize, is_word: fn(char) -> bool) -> usize {
+    let classify = |grapheme: &str| grapheme.chars().next().is_some_and(is_word);
+
+    buffer[cursor..].grapheme_indices(true)
+        .skip_while(|(_, grapheme)| !classify(grapheme))
+        .find(|(_, grapheme)| !classify(grapheme))
+        .map_or(buffer.len(), |(idx, _)| cursor + idx)
+}
+
+// KillRing keeps the text removed by the kill commands, kills that directly follow each other are
+// joined into one entry like in emacs
+#[derive(Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+    // index is the entry Ctrl-Y inserts, Alt-Y moves it to older entries
+    index: usize,
+}
+
+impl KillRing {
+    pub fn new() -> KillRing {
+        KillRing {
+            entries: Vec::new(),
+            index: 0,
+        }
+    }
+
+    // kill adds killed text, when append is set it is joined with the previous kill, in front of
+    // it if the text was killed backwards
+    pub fn kill(&mut self, text: &str, append: bool, backwards: bool) {
+        if text.is_empty() {
+            return;
+        }
+
+        match self.entries.last_mut() {
+            Some(last) if append => {
+                if backwards {
+                    last.insert_str(0, text);
+                } else {
+                    last.push_str(text);
+                }
+            },
+            _ => {
+                self.entries.push(text.to_string());
+                if self.entries.len() > KILL_RING_SIZE {
+                    self.entries.remove(0);
+                }
+            },
+        }
+
+        self.index = self.entries.len() - 1;
+    }
+
+    pub fn yank(&self) -> Option<&str> {
+        self.entries.get(self.index).map(String::as_str)
+    }
+
+    // rotate moves on to the kill before the one that was yanked last, past the oldest kill it
+    // wraps around to the newest
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        self.index = self.index.checked_sub(1).unwrap_or(self.entries.len() - 1);
+        self.yank()
+    }
+}
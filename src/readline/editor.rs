@@ -0,0 +1,90 @@
+use std::process::{self, Command};
+use std::path::{Path, PathBuf};
+use std::io::{self, Write};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::env;
+
+
+// editor returns the editor to use and its arguments, $VISUAL comes before $EDITOR and vi is used
+// when neither is set
+fn editor() -> Vec<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_default();
+
+    let words = editor.split_whitespace().map(str::to_string).collect::<Vec<String>>();
+    if words.is_empty() {
+        return vec![String::from("vi")];
+    }
+
+    words
+}
+
+fn open(path: &Path) -> Result<(), String> {
+    let editor = editor();
+
+    let status = Command::new(&editor[0])
+        .args(&editor[1..])
+        .arg(path)
+        .status()
+        .map_err(|err| format!("{}: {err}", editor[0]))?;
+
+    if !status.success() {
+        return Err(format!("{} exited with {status}", editor[0]));
+    }
+
+    Ok(())
+}
+
+// private creates a directory in the temporary directory that only the user can get into, mkdir
+// fails on anything that is already there so a planted file or symlink is never used
+fn private() -> Result<PathBuf, String> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos()).unwrap_or_default();
+
+    for attempt in 0..100 {
+        let path = env::temp_dir().join(format!("nsh-edit-{}-{nanos}-{attempt}", process::id()));
+
+        match DirBuilder::new().mode(0o700).create(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.to_string()),
+            Err(_) => {},
+        }
+    }
+
+    Err(String::from("no temporary directory could be created"))
+}
+
+// edit_in writes the text to a new file in the directory, opens it in the editor and reads it back
+fn edit_in(directory: &Path, text: &str) -> Result<String, String> {
+    let path = directory.join("line.nsh");
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .map_err(|err| err.to_string())?;
+
+    if let Err(err) = file.write_all(format!("{text}\n").as_bytes()) {
+        return Err(err.to_string());
+    }
+    drop(file);
+
+    open(&path)?;
+    fs::read_to_string(&path).map_err(|err| err.to_string())
+}
+
+// edit opens the text in the editor in the foreground and returns it as it was saved, the newline
+// editors put at the end of the file is taken off again
+pub fn edit(text: &str) -> Result<String, String> {
+    let directory = private()?;
+
+    // the directory goes away along with whatever the editor left in it however editing went
+    let edited = edit_in(&directory, text);
+    let _ = fs::remove_dir_all(&directory);
+
+    let edited = edited?;
+    Ok(edited.strip_suffix('\n').unwrap_or(&edited).to_string())
+}
@@ -0,0 +1,50 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+
+// the cursor is a byte index into the line that always sits between two grapheme clusters, so a
+// character with combining marks or an emoji made of several code points is moved over, deleted
+// and drawn as the one character it looks like
+
+// previous returns where the grapheme before idx starts
+pub fn previous(text: &str, idx: usize) -> usize {
+    text[..idx].grapheme_indices(true).next_back().map_or(0, |(start, _)| start)
+}
+
+// next returns where the grapheme after idx ends
+pub fn next(text: &str, idx: usize) -> usize {
+    text[idx..].graphemes(true).next().map_or(idx, |grapheme| idx + grapheme.len())
+}
+
+// grapheme_width is how many columns a grapheme takes up, it is decided by its first character
+// except for emoji presentation sequences which are always wide
+fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.contains('\u{fe0f}') {
+        return 2;
+    }
+
+    grapheme.chars().next().and_then(UnicodeWidthChar::width).unwrap_or(0)
+}
+
+// width returns how many columns the text takes up in the terminal
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+pub fn graphemes(text: &str) -> Vec<String> {
+    text.graphemes(true).map(str::to_string).collect()
+}
+
+// offset returns where in the text the given column is, or the end of the text when it is shorter
+pub fn offset(text: &str, column: usize) -> usize {
+    let mut width = 0;
+
+    for (idx, grapheme) in text.grapheme_indices(true) {
+        width += grapheme_width(grapheme);
+        if width > column {
+            return idx;
+        }
+    }
+
+    text.len()
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+use std::fs;
+
+use console::Style;
+use lib_lexin::Token;
+
+use crate::config::Config;
+use crate::hash::{self, CommandHash};
+use crate::lexer::{self, KEYWORDS};
+
+
+// STYLES are the words a style can be made of, they are joined with dots like `green.bold`
+const STYLES: [&str; 26] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white", "bright",
+    "on_black", "on_red", "on_green", "on_yellow", "on_blue", "on_magenta", "on_cyan", "on_white", "on_bright",
+    "bold", "dim", "underlined", "blink", "blink_fast", "reverse", "hidden", "strikethrough",
+];
+
+// Theme is how the parts of the line are coloured, it is changed with `set color_<part> <style>`
+pub struct Theme {
+    pub command: String,
+    pub unknown: String,
+    pub string: String,
+    pub variable: String,
+    pub operator: String,
+    pub path: String,
+    pub paste: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::new()
+    }
+}
+
+impl Theme {
+    pub fn new() -> Theme {
+        Theme {
+            command: String::from("green"),
+            unknown: String::from("red"),
+            string: String::from("yellow"),
+            variable: String::from("cyan"),
+            operator: String::from("magenta"),
+            path: String::from("underlined"),
+            paste: String::from("reverse"),
+        }
+    }
+
+    // style checks a style from the config, numbers are one of the 256 colours
+    pub fn style(style: &str) -> Result<String, String> {
+        let valid = |part: &str| STYLES.contains(&part) || part.trim_start_matches("on_").parse::<u8>().is_ok();
+
+        match style.split('.').find(|part| !valid(part)) {
+            Some(part) => Err(format!("unknown style `{part}`")),
+            None => Ok(style.to_string()),
+        }
+    }
+}
+
+// known tells if a command can be run, that is a builtin, an alias or an executable
+fn known(command: &str, hash: &mut CommandHash, config: &Config) -> bool {
+    if command.contains('/') {
+        return hash::is_executable(Path::new(command));
+    }
+
+    KEYWORDS.contains(&command)
+        || config.alias.iter().any(|(alias, _)| alias.as_string() == command)
+        || hash.get(command).is_some()
+        || hash.names().iter().any(|name| name == command)
+}
+
+// exists tells if an argument names a file or directory, `~` is expanded like the parser does
+fn exists(argument: &str) -> bool {
+    let path = match argument.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => std::env::var("HOME").unwrap_or_default() + rest,
+        _ => argument.to_string(),
+    };
+
+    fs::metadata(path).is_ok()
+}
+
+// styles returns the tokens of the line along with how they are coloured, the first word of every
+// command is coloured by whether it exists and the rest by what kind of token it is
+fn styles<'a>(buffer: &str, hash: &mut CommandHash, config: &'a Config) -> Vec<(usize, usize, &'a str)> {
+    let theme = &config.theme;
+    let mut styles: Vec<(usize, usize, &str)> = Vec::new();
+    let mut end = 0;
+    let mut command = true;
+
+    for span in lexer::spans(buffer) {
+        let text = &buffer[span.start..span.end];
+
+        // a new line is a new command
+        if buffer[end..span.start].contains('\n') {
+            command = true;
+        }
+
+        let style = match &span.token {
+            Token::Symbol(..) => theme.operator.as_str(),
+            Token::Section(name, ..) if name == "string" => theme.string.as_str(),
+            Token::Section(..) => theme.variable.as_str(),
+            Token::Keyword(..) if command => theme.command.as_str(),
+            _ if command && known(text, hash, config) => theme.command.as_str(),
+            _ if command => theme.unknown.as_str(),
+            _ if exists(text) => theme.path.as_str(),
+            _ => "",
+        };
+
+        // `&` starts the next command and an environment variable is set in place of a command
+        command = match &span.token {
+            Token::Symbol(_, name, _) => name != "Equal",
+            _ => false,
+        };
+
+        styles.push((span.start, span.end, style));
+        end = span.end;
+    }
+
+    styles
+}
+
+// line colours the line being typed, what was just pasted is marked on top of the other colours
+pub fn line(buffer: &str, pasted: Option<(usize, usize)>, hash: &mut CommandHash, config: &Config) -> String {
+    let parts = if config.highlight { styles(buffer, hash, config) } else { Vec::new() };
+    if parts.is_empty() && pasted.is_none() {
+        return buffer.to_string();
+    }
+
+    // the text between the tokens is left as it is
+    let mut filled: Vec<(usize, usize, &str)> = Vec::new();
+    let mut end = 0;
+
+    for part in parts {
+        if part.0 > end {
+            filled.push((end, part.0, ""));
+        }
+        end = part.1;
+        filled.push(part);
+    }
+
+    if end < buffer.len() {
+        filled.push((end, buffer.len(), ""));
+    }
+
+    let mut line = String::new();
+    for (start, end, style) in filled {
+        // a part is split where the paste starts and ends so that only the pasted text is marked
+        let (from, to) = pasted.map_or((end, end), |(from, to)| (from.clamp(start, end), to.clamp(start, end)));
+
+        for (start, end, pasted) in [(start, from, false), (from, to, true), (to, end, false)] {
+            if start == end {
+                continue;
+            }
+
+            let style = match (pasted, style) {
+                (false, _) => style.to_string(),
+                (true, "") => config.theme.paste.clone(),
+                (true, _) => format!("{style}.{}", config.theme.paste),
+            };
+            line.push_str(&Style::from_dotted_str(&style).apply_to(&buffer[start..end]).to_string());
+        }
+    }
+
+    line
+}
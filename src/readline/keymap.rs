@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use console::Key;
+
+
+// Action is a named editor command that keys are bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    BeginningOfLine,
+    EndOfLine,
+    BackwardChar,
+    ForwardChar,
+    BackwardWord,
+    ForwardWord,
+    BackwardDeleteChar,
+    DeleteChar,
+    UnixWordRubout,
+    BackwardKillWord,
+    KillWord,
+    KillLine,
+    UnixLineDiscard,
+    Yank,
+    YankPop,
+    TransposeChars,
+    ClearScreen,
+    Complete,
+    PreviousHistory,
+    NextHistory,
+    HistorySearchBackward,
+    HistorySearchForward,
+    AcceptLine,
+    Newline,
+    Undo,
+    Redo,
+    EditCommandLine,
+}
+
+pub const ACTIONS: [(&str, Action); 27] = [
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("end-of-line", Action::EndOfLine),
+    ("backward-char", Action::BackwardChar),
+    ("forward-char", Action::ForwardChar),
+    ("backward-word", Action::BackwardWord),
+    ("forward-word", Action::ForwardWord),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("delete-char", Action::DeleteChar),
+    ("unix-word-rubout", Action::UnixWordRubout),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("kill-word", Action::KillWord),
+    ("kill-line", Action::KillLine),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("yank", Action::Yank),
+    ("yank-pop", Action::YankPop),
+    ("transpose-chars", Action::TransposeChars),
+    ("clear-screen", Action::ClearScreen),
+    ("complete", Action::Complete),
+    ("previous-history", Action::PreviousHistory),
+    ("next-history", Action::NextHistory),
+    ("history-search-backward", Action::HistorySearchBackward),
+    ("history-search-forward", Action::HistorySearchForward),
+    ("accept-line", Action::AcceptLine),
+    ("newline", Action::Newline),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("edit-command-line", Action::EditCommandLine),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|(action, _)| *action == name).map(|(_, action)| *action)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = ACTIONS.iter().find(|(_, action)| action == self).map_or("", |(name, _)| name);
+        write!(f, "{name}")
+    }
+}
+
+// Binding is what a key sequence does, either an editor action or a command whose output is
+// inserted into the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    Action(Action),
+    Command(String),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Action(action) => write!(f, "{action}"),
+            Binding::Command(command) => write!(f, "run {command:?}"),
+        }
+    }
+}
+
+const NAMED_KEYS: [(&str, Key); 14] = [
+    ("Up", Key::ArrowUp),
+    ("Down", Key::ArrowDown),
+    ("Left", Key::ArrowLeft),
+    ("Right", Key::ArrowRight),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("Delete", Key::Del),
+    ("Insert", Key::Insert),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("Tab", Key::Tab),
+    ("BackTab", Key::BackTab),
+    ("Enter", Key::Enter),
+    ("Backspace", Key::Backspace),
+];
+
+// the modified arrow keys as xterm sends them, 5 is control and 3 or 9 is alt
+const MODIFIED_KEYS: [(&str, &str); 8] = [
+    ("[1;5C", "C-Right"),
+    ("[1;5D", "C-Left"),
+    ("[1;3C", "M-Right"),
+    ("[1;3D", "M-Left"),
+    ("[1;9C", "M-Right"),
+    ("[1;9D", "M-Left"),
+    ("[1;5A", "C-Up"),
+    ("[1;5B", "C-Down"),
+];
+
+// name turns a key into the notation bindings are written in, `C-a` for control, `M-f` for alt
+// and names like `Up` or `Space` for everything that isnt printable
+pub fn name(key: &Key) -> Option<String> {
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, named)| named == key) {
+        return Some(name.to_string());
+    }
+
+    let name = match key {
+        Key::Escape => String::from("Escape"),
+        Key::Char(' ') => String::from("Space"),
+        Key::Char(character @ '\x01'..='\x1a') => format!("C-{}", (*character as u8 + b'a' - 1) as char),
+        Key::Char(character @ ('\x00' | '\x1b'..='\x1f')) => format!("C-{}", (*character as u8 + b'@') as char),
+        Key::Char(character) => character.to_string(),
+        Key::UnknownEscSeq(sequence) => {
+            let sequence = sequence.iter().collect::<String>();
+
+            if let Some((_, name)) = MODIFIED_KEYS.iter().find(|(modified, _)| *modified == sequence) {
+                name.to_string()
+            } else if sequence == "\x7f" {
+                String::from("M-Backspace")
+            } else if sequence == "\r" || sequence == "\n" {
+                String::from("M-Enter")
+            } else if sequence.chars().count() == 1 {
+                format!("M-{sequence}")
+            } else {
+                format!("Escape-{sequence}")
+            }
+        },
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+// console already turns these control keys into other keys so they can only be told apart by
+// their other name
+const ALIASES: [(&str, &str); 6] = [
+    ("C-a", "Home"),
+    ("C-e", "End"),
+    ("C-h", "Backspace"),
+    ("C-i", "Tab"),
+    ("C-j", "Enter"),
+    ("C-m", "Enter"),
+];
+
+// valid tells if a name can come out of name, so typos in bindings are caught when binding
+fn valid(name: &str) -> bool {
+    let single = |rest: &str| rest.chars().count() == 1;
+
+    NAMED_KEYS.iter().any(|(named, _)| *named == name)
+        || MODIFIED_KEYS.iter().any(|(_, modified)| *modified == name)
+        || matches!(name, "Escape" | "Space" | "M-Backspace" | "M-Enter")
+        || name.strip_prefix("C-").is_some_and(|rest| single(rest) && rest.is_ascii())
+        || name.strip_prefix("M-").is_some_and(single)
+        || name.starts_with("Escape-")
+        || (single(name) && name != " ")
+}
+
+pub enum Lookup {
+    // the keys so far start a longer binding
+    Pending,
+    Found(Binding),
+    // the keys arent bound, the flag is set when it was just the one key so it can be inserted
+    Unbound(bool),
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<String>, Binding>,
+    pending: Vec<String>,
+}
+
+impl Keymap {
+    // emacs returns the default bindings
+    pub fn emacs() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+            pending: Vec::new(),
+        };
+
+        let defaults = [
+            ("Home", Action::BeginningOfLine),
+            ("End", Action::EndOfLine),
+            ("C-b", Action::BackwardChar),
+            ("Left", Action::BackwardChar),
+            ("C-f", Action::ForwardChar),
+            ("Right", Action::ForwardChar),
+            ("M-b", Action::BackwardWord),
+            ("C-Left", Action::BackwardWord),
+            ("M-Left", Action::BackwardWord),
+            ("M-f", Action::ForwardWord),
+            ("C-Right", Action::ForwardWord),
+            ("M-Right", Action::ForwardWord),
+            ("Backspace", Action::BackwardDeleteChar),
+            ("Delete", Action::DeleteChar),
+            ("C-w", Action::UnixWordRubout),
+            ("M-Backspace", Action::BackwardKillWord),
+            ("M-d", Action::KillWord),
+            ("C-k", Action::KillLine),
+            ("C-u", Action::UnixLineDiscard),
+            ("C-y", Action::Yank),
+            ("M-y", Action::YankPop),
+            ("C-t", Action::TransposeChars),
+            ("C-l", Action::ClearScreen),
+            ("Tab", Action::Complete),
+            ("Up", Action::PreviousHistory),
+            ("C-p", Action::PreviousHistory),
+            ("Down", Action::NextHistory),
+            ("C-n", Action::NextHistory),
+            ("C-r", Action::HistorySearchBackward),
+            ("C-s", Action::HistorySearchForward),
+            ("Enter", Action::AcceptLine),
+            ("M-Enter", Action::Newline),
+            ("C-_", Action::Undo),
+            ("C-z", Action::Undo),
+            ("M-_", Action::Redo),
+            ("C-x C-e", Action::EditCommandLine),
+        ];
+
+        // Ctrl-A and Ctrl-E arrive as Home and End
+        for (sequence, action) in defaults {
+            if let Ok(sequence) = Keymap::sequence(sequence) {
+                keymap.bindings.insert(sequence, Binding::Action(action));
+            }
+        }
+
+        keymap
+    }
+
+    fn sequence(sequence: &str) -> Result<Vec<String>, String> {
+        let names = sequence.split_whitespace()
+            .map(|name| ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, key)| key))
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+
+        if names.is_empty() {
+            return Err(String::from("expected a key sequence"));
+        }
+
+        match names.iter().find(|name| !valid(name)) {
+            Some(name) => Err(format!("unknown key `{name}`")),
+            None => Ok(names),
+        }
+    }
+
+    pub fn bind(&mut self, sequence: &str, binding: Binding) -> Result<(), String> {
+        self.bindings.insert(Keymap::sequence(sequence)?, binding);
+        Ok(())
+    }
+
+    pub fn unbind(&mut self, sequence: &str) -> Result<(), String> {
+        match self.bindings.remove(&Keymap::sequence(sequence)?) {
+            Some(_) => Ok(()),
+            None => Err(format!("`{sequence}` isnt bound")),
+        }
+    }
+
+    pub fn bindings(&self) -> Vec<(String, &Binding)> {
+        let mut bindings = self.bindings.iter()
+            .map(|(sequence, binding)| (sequence.join(" "), binding))
+            .collect::<Vec<(String, &Binding)>>();
+
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    // lookup adds a key to the sequence typed so far, a sequence that is the start of a longer
+    // binding waits for more keys
+    pub fn lookup(&mut self, key: &Key) -> Lookup {
+        let Some(name) = name(key) else {
+            self.pending.clear();
+            return Lookup::Unbound(false);
+        };
+        self.pending.push(name);
+
+        let longer = self.bindings.keys()
+            .any(|sequence| sequence.len() > self.pending.len() && sequence.starts_with(&self.pending));
+        if longer {
+            return Lookup::Pending;
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        match self.bindings.get(&pending) {
+            Some(binding) => Lookup::Found(binding.clone()),
+            None => Lookup::Unbound(pending.len() == 1),
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.pending.clear();
+    }
+}
@@ -0,0 +1,113 @@
+use console::{style, measure_text_width, truncate_str};
+
+use crate::completion::Candidate;
+
+
+// Menu holds the candidates of a completion that couldnt be resolved to a single word, it is
+// shown on the second tab and lets the user cycle through the candidates
+pub struct Menu {
+    // start and end are the byte range in the buffer that the current selection occupies
+    pub start: usize,
+    pub end: usize,
+    pub candidates: Vec<Candidate>,
+    pub selected: Option<usize>,
+    pub visible: bool,
+}
+
+impl Menu {
+    pub fn new(start: usize, end: usize, candidates: Vec<Candidate>) -> Menu {
+        Menu {
+            start,
+            end,
+            candidates,
+            selected: None,
+            visible: false,
+        }
+    }
+
+    fn column_width(&self) -> usize {
+        self.candidates.iter()
+            .map(|candidate| measure_text_width(&candidate.display))
+            .max()
+            .unwrap_or(0) + 2
+    }
+
+    fn has_descriptions(&self) -> bool {
+        self.candidates.iter().any(|candidate| candidate.description.is_some())
+    }
+
+    pub fn columns(&self, width: usize) -> usize {
+        // descriptions need the rest of the line so every candidate gets its own row
+        if self.has_descriptions() {
+            return 1;
+        }
+
+        (width / self.column_width()).max(1)
+    }
+
+    // step moves the selection by amount candidates and wraps around at either end
+    pub fn step(&mut self, amount: usize, forward: bool) -> &Candidate {
+        let len = self.candidates.len();
+
+        let selected = match self.selected {
+            Some(selected) if forward => (selected + amount) % len,
+            Some(selected) => (selected + len - amount % len) % len,
+            None if forward => 0,
+            None => len - 1,
+        };
+
+        self.selected = Some(selected);
+        &self.candidates[selected]
+    }
+
+    // render lays the candidates out in rows that fit the terminal, only height rows are
+    // returned and they are scrolled so the selection stays visible
+    pub fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let columns = self.columns(width);
+        let column_width = self.column_width();
+        let rows = self.candidates.len().div_ceil(columns);
+
+        let height = height.max(1);
+        let first = match self.selected {
+            Some(selected) if selected / columns >= height => selected / columns + 1 - height,
+            _ => 0,
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+        for row in first..rows.min(first + height) {
+            let mut line = String::new();
+
+            for column in 0..columns {
+                let index = row * columns + column;
+                let Some(candidate) = self.candidates.get(index) else {
+                    break;
+                };
+
+                let padding = column_width - measure_text_width(&candidate.display);
+                let cell = format!("{}{}", candidate.display, " ".repeat(padding));
+
+                if self.selected == Some(index) {
+                    line.push_str(&style(cell).reverse().to_string());
+                } else {
+                    line.push_str(&cell);
+                }
+
+                if let Some(description) = &candidate.description {
+                    let space = width.saturating_sub(column_width + 3);
+                    let description = truncate_str(description, space, "…");
+                    line.push_str(&style(format!("-- {description}")).dim().to_string());
+                }
+            }
+
+            // a candidate wider than the terminal is cut off so every line stays on a single row
+            lines.push(truncate_str(line.trim_end(), width, "…").to_string());
+        }
+
+        if rows > first + height {
+            let more = style(format!("-- {} more rows --", rows - first - height)).dim().to_string();
+            lines.push(truncate_str(&more, width, "…").to_string());
+        }
+
+        lines
+    }
+}
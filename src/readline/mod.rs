@@ -1,18 +1,72 @@
-use std::io::{self, Write, BufWriter};
-use std::fs::{self, File};
+mod menu;
+mod search;
+mod edit;
+mod vi;
+mod keymap;
+mod grapheme;
+mod render;
+mod highlight;
+mod undo;
+mod editor;
+
+use std::io::{self, Write, IsTerminal};
+use std::os::fd::AsFd;
 use std::process;
+use std::env;
+use std::fmt;
 
-use console::{Term, Key};
+use console::{Term, Key, style, strip_ansi_codes};
+use nix::sys::termios::{self, SetArg};
 
 use crate::completion;
+use crate::config::Config;
+use crate::hash::CommandHash;
+use crate::history::{History, Entry};
+use crate::lexer;
+use crate::prompt::Prompt;
+use crate::signals;
+
+use menu::Menu;
+use search::Search;
+use edit::KillRing;
+use vi::{Vi, Mode, Step};
+use keymap::{Keymap, Lookup, Binding, Action};
+use undo::Undo;
+
+pub use keymap::ACTIONS;
+pub use highlight::Theme;
+pub use editor::edit;
 
 
+// PASTE_START and PASTE_END are the sequences the terminal wraps pasted text in while bracketed
+// paste is on
+const PASTE_START: &str = "[200~";
+const PASTE_END: &str = "[201~";
+// MAX_PASTE is how much pasted text is kept, the rest of a bigger paste is dropped
+const MAX_PASTE: usize = 1024 * 1024;
+
 #[derive(PartialEq)]
 enum Direction {
     Right,
     Left,
 }
 
+// Editing is the set of keys used to edit the line, it is changed with `set editing <Editing>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editing {
+    Emacs,
+    Vi,
+}
+
+impl fmt::Display for Editing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Editing::Emacs => write!(f, "emacs"),
+            Editing::Vi => write!(f, "vi"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ReadLineError {
     Flush(String),
@@ -20,6 +74,7 @@ pub enum ReadLineError {
     Read(String),
     Cursor(String),
     Completion(String),
+    History(String),
 }
 
 impl ReadLineError {
@@ -30,6 +85,7 @@ impl ReadLineError {
             Self::Read(message) =>       message.to_string(),
             Self::Cursor(message) =>     message.to_string(),
             Self::Completion(message) => message.to_string(),
+            Self::History(message) =>    message.to_string(),
         };
     }
 }
@@ -38,7 +94,38 @@ pub struct ReadLine {
     pub buffer: String,
     term: Term,
     cursor: usize,
-    history: Vec<String>,
+    history: History,
+    // history_path is the file every entry is appended to as soon as it is added
+    history_path: Option<String>,
+    menu: Option<Menu>,
+    help: completion::HelpCache,
+    search: Option<Search>,
+    last_search: String,
+    history_index: usize,
+    // history_prefix is what was typed before walking the history, only entries starting with it
+    // are visited and it is restored when walking past the newest entry
+    history_prefix: Option<String>,
+    kill_ring: KillRing,
+    // killing is set while kills follow each other so they end up in one kill ring entry and
+    // yanked is where the last yank put its text so Alt-Y can replace it
+    killing: bool,
+    yanked: Option<(usize, usize)>,
+    // vi is only set while the vi editing mode is used
+    vi: Option<Vi>,
+    keymap: Keymap,
+    // command is set when a key bound to a command stops reading the line, resume keeps the line
+    // as it is for when reading carries on with the output of the command inserted
+    command: Option<String>,
+    resume: bool,
+    // drawn is the text on the screen from the start of the prompt up to the cursor, the next
+    // redraw works out from it how many rows to go up even when the terminal was resized since
+    drawn: String,
+    // pasted is where the text of the last paste is, it is highlighted until the next key
+    pasted: Option<(usize, usize)>,
+    undo: Undo,
+    // queued is a line `fc` wants to run once the current one is done
+    queued: Option<String>,
+    prompt: Prompt,
 }
 
 impl ReadLine {
@@ -47,28 +134,92 @@ impl ReadLine {
             buffer: String::new(),
             term: Term::stdout(),
             cursor: 0,
-            history: Vec::new(),
+            history: History::new(),
+            history_path: None,
+            menu: None,
+            help: completion::HelpCache::new(),
+            search: None,
+            last_search: String::new(),
+            history_index: 0,
+            history_prefix: None,
+            kill_ring: KillRing::new(),
+            killing: false,
+            yanked: None,
+            vi: None,
+            keymap: Keymap::emacs(),
+            command: None,
+            resume: false,
+            drawn: String::new(),
+            pasted: None,
+            undo: Undo::new(),
+            queued: None,
+            prompt: Prompt::default(),
         }
     }
 
-    pub fn clear_history(&mut self) {
-        self.history.clear();
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    // clear_history forgets every entry and empties the history file
+    pub fn clear_history(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.history.clear(self.history_path.as_deref())
+    }
+
+    // delete_history forgets an entry and takes it out of the history file, the records other
+    // sessions wrote to the file are kept
+    pub fn delete_history(&mut self, index: usize) -> Result<bool, Box<dyn std::error::Error>> {
+        self.history.remove(self.history_path.as_deref(), index)
     }
 
-    pub fn save_history(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let file = File::create(path)?;
-        let mut buf = BufWriter::new(file);
+    // load_history reads the history file and remembers it so added entries are appended to it,
+    // a missing file is created. history that couldnt be loaded isnt written either
+    pub fn load_history(&mut self, path: &str, size: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.history.load(path, size)?;
+        self.history_path = Some(path.to_string());
+        Ok(())
+    }
+
+    // add_history records a line that was run, ignored lines are neither kept nor written, with
+    // shared history the entries other sessions wrote in the meantime are taken in before it
+    pub fn add_history(&mut self, entry: &Entry, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        if self.history.ignored(entry) || config.history_size == 0 {
+            return Ok(());
+        }
+
+        if let Some(path) = &self.history_path {
+            let unseen = self.history.append(path, entry, config.history_size)?;
 
-        for line in &self.history {
-            buf.write((line.to_owned() + "\n").as_bytes())?;
+            if config.share_history {
+                for other in unseen {
+                    self.history.add(other, config.history_size);
+                }
+            }
         }
 
+        self.history.add(entry.clone(), config.history_size);
         Ok(())
     }
 
-    pub fn load_history(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        for line in fs::read_to_string(path)?.lines() {
-            self.history.push(line.to_string());
+    // update_history takes in the entries other sessions appended to the history file, it only
+    // happens before a line is read so a walk through the history never shifts under the user
+    fn update_history(&mut self, config: &Config) -> Result<(), ReadLineError> {
+        let Some(path) = &self.history_path else {
+            return Ok(());
+        };
+
+        if !config.share_history {
+            return Ok(());
+        }
+
+        let newest = self.history_index == self.history.len();
+        if let Err(err) = self.history.update(path, config.history_size) {
+            return Err(ReadLineError::History(err.to_string()));
+        }
+
+        // a walk that hasnt started yet starts from the newest entry including the new ones
+        if newest {
+            self.history_index = self.history.len();
         }
 
         Ok(())
@@ -81,147 +232,899 @@ impl ReadLine {
         }
     }
 
-    fn output(&self, prompt: &str) -> Result<(), ReadLineError> {
-        if let Err(err) = self.term.clear_line() {
+    fn output(&mut self, prompt: &str, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        self.draw(prompt, true, hash, config)
+    }
+
+    // finish draws the line a last time without the suggestion and the menu and moves below it, a
+    // transient prompt takes the place of the prompt from here on
+    fn finish(&mut self, prompt: &str, end: &str, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        let transient = self.prompt.transient.clone();
+        self.draw(transient.as_deref().unwrap_or(prompt), false, hash, config)?;
+        println!("{end}");
+
+        self.drawn.clear();
+        Ok(())
+    }
+
+    // draw redraws the prompt and the line from where the prompt starts, the line can take up
+    // several rows when it wraps or has newlines in it
+    fn draw(&mut self, prompt: &str, editing: bool, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        let width = usize::from(self.term.size().1).max(1);
+
+        let (rows, _) = render::cursor(&self.drawn, width);
+        if let Err(err) = self.term.move_cursor_up(rows) {
+            return Err(ReadLineError::Cursor(err.to_string()));
+        }
+        print!("\r");
+
+        if let Err(err) = self.term.clear_to_end_of_screen() {
             return Err(ReadLineError::Clear(err.to_string()));
         }
 
-        print!("{prompt}{}", self.buffer);
+        // while searching the prompt is replaced by the search and the match is highlighted
+        let pasted = self.pasted.filter(|_| config.highlight_paste);
+        let (prompt, line) = match &self.search {
+            Some(search) => (search.prompt(), search.highlight(&self.buffer)),
+            None => (prompt.to_string(), highlight::line(&self.buffer, pasted, hash, config)),
+        };
+        print!("{prompt}{line}");
+
+        let prompt = strip_ansi_codes(&prompt).to_string();
+        let mut text = prompt.clone() + &self.buffer;
+
+        // the suggestion is drawn after the buffer and the cursor is put back in front of it
+        let suggestion = if editing { self.suggestion().unwrap_or_default() } else { String::new() };
+        if !suggestion.is_empty() {
+            print!("{}", style(&suggestion).dim());
+            text.push_str(&suggestion);
+        }
+
+        // a filled up row is left by hand so the terminal and the layout agree on where the cursor is
+        let (mut row, column) = render::position(&text, width);
+        if editing && column >= width {
+            println!();
+            row += 1;
+        }
+
+        if let Some(menu) = self.menu.as_ref().filter(|menu| menu.visible && editing) {
+            let (height, _) = self.term.size();
+            let lines = menu.render(width, (height as usize).saturating_sub(2));
+
+            // every line starts on a row of its own and takes as many rows as it wraps onto
+            for line in &lines {
+                print!("\n{line}");
+                row += render::position(&strip_ansi_codes(line), width).0 + 1;
+            }
+        }
+
+        // the right prompt goes at the end of the row the line starts on, it is left out as soon as
+        // the line or the suggestion would run into it
+        let right = if self.search.is_some() || (!editing && self.prompt.transient.is_some()) { "" } else { &self.prompt.right };
+        let right_width = grapheme::width(&strip_ansi_codes(right));
+
+        let first = text[prompt.len()..].find('\n').map_or(text.as_str(), |idx| &text[..prompt.len() + idx]);
+        let (start, _) = render::cursor(&prompt, width);
+        let (first_row, first_column) = render::position(first, width);
+
+        if !right.is_empty() && !right.contains('\n') && first_row == start && first_column + right_width + 2 <= width {
+            self.move_rows(row, start)?;
+            print!("\r");
+            if let Err(err) = self.term.move_cursor_right(width - right_width - 1) {
+                return Err(ReadLineError::Cursor(err.to_string()));
+            }
+            print!("{right}");
+            row = start;
+        }
 
+        // go back to the cursor, or to the end of the line when it is done
+        let drawn = if editing { prompt + &self.buffer[..self.cursor] } else { text };
+        let (cursor_row, cursor_column) = if editing { render::cursor(&drawn, width) } else { render::position(&drawn, width) };
+
+        print!("\r");
+        self.move_rows(row, cursor_row)?;
+        if let Err(err) = self.term.move_cursor_right(cursor_column) {
+            return Err(ReadLineError::Cursor(err.to_string()));
+        }
+
+        self.drawn = drawn;
         self.flush()
     }
 
+    // move_rows moves the cursor up or down from one row of the line to another
+    fn move_rows(&self, from: usize, to: usize) -> Result<(), ReadLineError> {
+        let moved = if from > to { self.term.move_cursor_up(from - to) } else { self.term.move_cursor_down(to - from) };
+
+        match moved {
+            Ok(()) => Ok(()),
+            Err(err) => Err(ReadLineError::Cursor(err.to_string())),
+        }
+    }
+
     fn backspace(&mut self) {
-        self.cursor -= 1;
-        self.buffer.remove(self.cursor);
+        let start = grapheme::previous(&self.buffer, self.cursor);
+        self.buffer.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn delete(&mut self) {
+        let end = grapheme::next(&self.buffer, self.cursor);
+        self.buffer.drain(self.cursor..end);
+    }
+
+    // kill removes the text between start and end and keeps it in the kill ring
+    fn kill(&mut self, start: usize, end: usize, appending: bool) {
+        let text = self.buffer.drain(start..end).collect::<String>();
+
+        self.kill_ring.kill(&text, appending, end <= self.cursor);
+        self.cursor = start;
+        self.killing = true;
+    }
+
+    fn yank(&mut self) {
+        if let Some(text) = self.kill_ring.yank().map(str::to_string) {
+            let start = self.cursor;
+            self.replace(start, start, &text);
+            self.yanked = Some((start, self.cursor));
+        }
+    }
+
+    // yank_pop replaces the text that was just yanked with the kill before it
+    fn yank_pop(&mut self, yanked: Option<(usize, usize)>) {
+        let Some((start, end)) = yanked else {
+            return;
+        };
+
+        if let Some(text) = self.kill_ring.rotate().map(str::to_string) {
+            self.replace(start, end, &text);
+            self.yanked = Some((start, self.cursor));
+        }
+    }
+
+    // transpose swaps the characters around the cursor and moves past them, at the end of the line
+    // the last two characters are swapped instead
+    fn transpose(&mut self) {
+        let mut cursor = self.cursor;
+        if cursor == self.buffer.len() {
+            cursor = grapheme::previous(&self.buffer, cursor);
+        }
+
+        let before = grapheme::previous(&self.buffer, cursor);
+        let end = grapheme::next(&self.buffer, cursor);
+        if before == cursor || end == cursor {
+            return;
+        }
+
+        let swapped = format!("{}{}", &self.buffer[cursor..end], &self.buffer[before..cursor]);
+        self.replace(before, end, &swapped);
+    }
+
+    // edit opens the line in the editor and takes back what was saved, the line is left on the
+    // screen above the editor and drawn again below it
+    fn edit(&mut self, prompt: &str, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        self.finish(prompt, "", hash, config)?;
+
+        // the editor gets the terminal the way commands get it
+        print!("\x1b[?2004l");
+        self.flush()?;
+        if let Err(err) = signals::restore_sigint() {
+            return Err(ReadLineError::Read(err.to_string()));
+        }
+
+        let edited = editor::edit(&self.buffer);
+
+        if let Err(err) = signals::ignore_sigint() {
+            return Err(ReadLineError::Read(err.to_string()));
+        }
+        print!("\x1b[?2004h");
+
+        match edited {
+            Ok(line) => {
+                self.buffer = line;
+                self.cursor = self.buffer.len();
+            },
+            // the line is kept so nothing that was typed is lost
+            Err(err) => println!("[ERROR]: Failed to edit the line -> `{err}`"),
+        }
+
+        Ok(())
+    }
+
+    // perform runs an editor action, it returns true once the line is accepted
+    fn perform(&mut self, action: Action, prompt: &str, hash: &mut CommandHash, config: &Config) -> Result<bool, ReadLineError> {
+        let killing = std::mem::take(&mut self.killing);
+        let yanked = self.yanked.take();
+
+        match action {
+            Action::BeginningOfLine => self.cursor = self.line_start(),
+            Action::EndOfLine => {
+                if !self.accept_suggestion(true) {
+                    self.cursor = self.line_end();
+                }
+            },
+            Action::BackwardChar => self.move_cursor(Direction::Left),
+            Action::ForwardChar => {
+                if !self.accept_suggestion(true) {
+                    self.move_cursor(Direction::Right);
+                }
+            },
+            Action::BackwardWord => self.cursor = edit::word_left(&self.buffer, self.cursor, edit::is_word),
+            // at the end of the line a word of the suggestion is taken instead
+            Action::ForwardWord => {
+                if !self.accept_suggestion(false) {
+                    self.cursor = edit::word_right(&self.buffer, self.cursor, edit::is_word);
+                }
+            },
+            Action::BackwardDeleteChar => {
+                if self.cursor > 0 {
+                    self.backspace();
+                }
+            },
+            Action::DeleteChar => self.delete(),
+            Action::UnixWordRubout => {
+                let start = edit::word_left(&self.buffer, self.cursor, edit::is_not_space);
+                self.kill(start, self.cursor, killing);
+            },
+            Action::BackwardKillWord => {
+                let start = edit::word_left(&self.buffer, self.cursor, edit::is_word);
+                self.kill(start, self.cursor, killing);
+            },
+            Action::KillWord => {
+                let end = edit::word_right(&self.buffer, self.cursor, edit::is_word);
+                self.kill(self.cursor, end, killing);
+            },
+            // at the end of a line the newline is killed so the next line is joined to it
+            Action::KillLine => {
+                let end = if self.cursor == self.line_end() { grapheme::next(&self.buffer, self.cursor) } else { self.line_end() };
+                self.kill(self.cursor, end, killing);
+            },
+            Action::UnixLineDiscard => self.kill(self.line_start(), self.cursor, killing),
+            Action::Yank => self.yank(),
+            Action::YankPop => self.yank_pop(yanked),
+            Action::TransposeChars => self.transpose(),
+            Action::ClearScreen => {
+                if let Err(err) = self.term.clear_screen() {
+                    return Err(ReadLineError::Clear(err.to_string()));
+                }
+                self.drawn.clear();
+            },
+            Action::Complete => self.complete(hash, config)?,
+            Action::PreviousHistory => {
+                if !self.move_line(true) {
+                    self.history_up();
+                }
+            },
+            Action::NextHistory => {
+                if !self.move_line(false) {
+                    self.history_down();
+                }
+            },
+            Action::HistorySearchBackward => self.start_search(false),
+            Action::HistorySearchForward => self.start_search(true),
+            Action::AcceptLine => {
+                // a line that stops inside of a string goes on on the next line
+                if lexer::incomplete(&self.buffer) {
+                    self.newline();
+                    return Ok(false);
+                }
+
+                // redraw once more so that a closed menu is cleared from the screen, the parts of
+                // the prompt that are still running arent needed anymore
+                self.finish(prompt, "", hash, config)?;
+                self.prompt.cancel();
+                return Ok(true);
+            },
+            Action::Newline => self.newline(),
+            Action::EditCommandLine => self.edit(prompt, hash, config)?,
+            Action::Undo | Action::Redo => {
+                let state = if action == Action::Undo { self.undo.undo() } else { self.undo.redo() };
+                if let Some((buffer, cursor)) = state {
+                    self.buffer = buffer;
+                    self.cursor = cursor;
+                }
+            },
+        }
+
+        Ok(false)
+    }
+
+    // self_insert handles a key that isnt bound to anything, it returns the character it inserted
+    fn self_insert(&mut self, key: &Key) -> Option<char> {
+        let Key::Char(character) = key else {
+            return None;
+        };
+
+        if *character == '\u{4}' {
+            println!("beta ^D");
+            process::exit(1);
+        }
+
+        if character.is_control() {
+            return None;
+        }
+
+        self.insert(character);
+        self.cursor += character.len_utf8();
+        Some(*character)
+    }
+
+    pub fn bind(&mut self, sequence: &str, action: &str) -> Result<(), String> {
+        let Some(action) = Action::from_name(action) else {
+            return Err(format!("unknown action `{action}`"));
+        };
+
+        self.keymap.bind(sequence, Binding::Action(action))
+    }
+
+    pub fn bind_command(&mut self, sequence: &str, command: &str) -> Result<(), String> {
+        self.keymap.bind(sequence, Binding::Command(command.to_string()))
+    }
+
+    pub fn unbind(&mut self, sequence: &str) -> Result<(), String> {
+        self.keymap.unbind(sequence)
+    }
+
+    pub fn bindings(&self) -> Vec<(String, String)> {
+        self.keymap.bindings().into_iter()
+            .map(|(sequence, binding)| (sequence, binding.to_string()))
+            .collect()
+    }
+
+    // take_command returns the command a key asked to run, input returns before the line is done
+    // when it is set
+    pub fn take_command(&mut self) -> Option<String> {
+        self.command.take()
+    }
+
+    pub fn queue(&mut self, line: String) {
+        self.queued = Some(line);
+    }
+
+    pub fn take_queued(&mut self) -> Option<String> {
+        self.queued.take()
+    }
+
+    // insert_output puts the output of a bound command into the line at the cursor, the next
+    // input carries on with the same line
+    pub fn insert_output(&mut self, output: &str) {
+        let output = output.trim_end_matches('\n').replace('\n', " ");
+
+        let cursor = self.cursor;
+        self.replace(cursor, cursor, &output);
     }
 
     fn insert(&mut self, character: &char) {
         self.buffer.insert(self.cursor, *character);
     }
 
+    fn newline(&mut self) {
+        self.insert(&'\n');
+        self.cursor += 1;
+    }
+
+    // line_start and line_end are where the line the cursor is on starts and ends in a buffer with
+    // several lines
+    fn line_start(&self) -> usize {
+        self.buffer[..self.cursor].rfind('\n').map_or(0, |idx| idx + 1)
+    }
+
+    fn line_end(&self) -> usize {
+        self.buffer[self.cursor..].find('\n').map_or(self.buffer.len(), |idx| self.cursor + idx)
+    }
+
+    // move_line moves the cursor to the line above or below keeping its column, it returns false
+    // on the first or the last line so the history is walked instead
+    fn move_line(&mut self, up: bool) -> bool {
+        let start = self.line_start();
+        let column = grapheme::width(&self.buffer[start..self.cursor]);
+
+        let target = if up {
+            if start == 0 {
+                return false;
+            }
+            self.buffer[..start - 1].rfind('\n').map_or(0, |idx| idx + 1)
+        } else {
+            let end = self.line_end();
+            if end == self.buffer.len() {
+                return false;
+            }
+            end + 1
+        };
+
+        let end = self.buffer[target..].find('\n').map_or(self.buffer.len(), |idx| target + idx);
+        self.cursor = target + grapheme::offset(&self.buffer[target..end], column);
+        true
+    }
+
     fn move_cursor(&mut self, direction: Direction) {
         if direction == Direction::Left {
-            if self.cursor != 0 {
-                self.cursor -= 1;
-            }
+            self.cursor = grapheme::previous(&self.buffer, self.cursor);
+        } else {
+            self.cursor = grapheme::next(&self.buffer, self.cursor);
+        }
+    }
+
+    fn history_get(&mut self, history_index: usize) {
+        if let Some(entry) = self.history.get(history_index) {
+            self.buffer = entry.line.clone();
+            self.cursor = entry.line.len();
+        }
+    }
+
+    fn history_up(&mut self) {
+        let prefix = self.history_prefix.get_or_insert_with(|| self.buffer.clone());
+
+        if let Some(index) = self.history.previous(self.history_index, prefix, &self.buffer) {
+            self.history_index = index;
+            self.history_get(index);
+        }
+    }
+
+    fn history_down(&mut self) {
+        let prefix = self.history_prefix.get_or_insert_with(|| self.buffer.clone());
+
+        if let Some(index) = self.history.next(self.history_index, prefix, &self.buffer) {
+            self.history_index = index;
+            self.history_get(index);
+        } else {
+            self.history_index = self.history.len();
+            self.buffer = prefix.clone();
+            self.cursor = self.buffer.len();
+        }
+    }
+
+    // suggestion returns the rest of the history entry that would complete the buffer, it is only
+    // offered while the cursor is at the end of the line
+    fn suggestion(&self) -> Option<String> {
+        if self.cursor != self.buffer.len() || self.search.is_some() || self.menu.as_ref().is_some_and(|menu| menu.visible) {
+            return None;
+        }
+
+        let cwd = env::current_dir().ok();
+        let cwd = cwd.as_ref().and_then(|cwd| cwd.to_str());
+
+        let line = self.history.suggest(&self.buffer, cwd)?;
+        Some(line[self.buffer.len()..].to_string())
+    }
+
+    fn accept_suggestion(&mut self, whole: bool) -> bool {
+        let Some(suggestion) = self.suggestion() else {
+            return false;
+        };
+
+        // a single word is the leading whitespace and everything up to the next space
+        let len = if whole {
+            suggestion.len()
         } else {
-            if self.cursor != self.buffer.len() {
-                self.cursor += 1;
+            let start = suggestion.len() - suggestion.trim_start().len();
+            suggestion[start..].find(' ').map_or(suggestion.len(), |end| start + end)
+        };
+
+        self.buffer.push_str(&suggestion[..len]);
+        self.cursor = self.buffer.len();
+        true
+    }
+
+    fn start_search(&mut self, forward: bool) {
+        self.search = Some(Search::new(forward, &self.history, &self.buffer, self.cursor));
+    }
+
+    fn end_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            if !search.query.is_empty() {
+                self.last_search = search.query;
             }
         }
     }
 
-    fn render_cursor(&mut self) -> Result<(), ReadLineError> {
-        if self.cursor != self.buffer.len() {
-            return match self.term.move_cursor_left(self.buffer.len() - self.cursor) {
-                Ok(_) => Ok(()),
-                Err(err) => Err(ReadLineError::Cursor(err.to_string())),
-            };
+    // search_key handles keys during an incremental search, it returns false when the key should
+    // be handled by the editor instead, which ends the search and keeps the line it found
+    fn search_key(&mut self, key: &Key) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+
+        match key {
+            Key::Char(character @ ('\x12' | '\x13')) => {
+                search.forward = *character == '\x13';
+
+                // searching again right away reuses the previous query like readline does
+                if search.query.is_empty() {
+                    search.query = self.last_search.clone();
+                }
+                search.again(&self.history);
+            },
+            Key::Char('\x07') => {
+                // Ctrl-G gives up on the search and puts the line back the way it was
+                (self.buffer, self.cursor) = search.original.clone();
+                self.search = None;
+                return true;
+            },
+            Key::Backspace => {
+                search.query.pop();
+                search.index = self.history.len();
+                search.search(&self.history);
+            },
+            Key::Char(character) if !character.is_control() => {
+                search.query.push(*character);
+                search.search(&self.history);
+            },
+            Key::Escape => {
+                self.end_search();
+                return true;
+            },
+            _ => {
+                self.end_search();
+                return false;
+            },
+        }
+
+        if !search.failed && !search.query.is_empty() {
+            if let Some(entry) = self.history.get(search.index) {
+                self.buffer = entry.line.clone();
+                self.cursor = search.position;
+            }
+        }
+
+        true
+    }
+
+    // wait waits for a key with the terminal in raw mode like console reads it, otherwise a
+    // terminal only has input once a whole line was typed
+    fn wait() -> io::Result<bool> {
+        let stdin = io::stdin();
+        if !stdin.is_terminal() {
+            return Ok(true);
+        }
+
+        let terminal = stdin.as_fd();
+        let original = termios::tcgetattr(terminal)?;
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        raw.output_flags = original.output_flags;
+
+        termios::tcsetattr(terminal, SetArg::TCSADRAIN, &raw)?;
+        let ready = signals::wait(terminal);
+        termios::tcsetattr(terminal, SetArg::TCSADRAIN, &original)?;
+
+        Ok(ready?)
+    }
+
+    // console gives up on escape sequences after three characters, so modified keys such as
+    // Alt-Right (`\x1b[1;3C`) arrive cut in half, read_key reads the rest of the sequence
+    fn read_key(&self) -> io::Result<Key> {
+        // a part of the prompt that was done while the line was drawn didnt interrupt anything, the
+        // wait tells about it instead
+        while !ReadLine::wait()? {
+            if signals::resized() | signals::woken() {
+                return Ok(Key::Unknown);
+            }
+        }
+
+        let key = match self.term.read_key() {
+            // console reports Ctrl-C as an interrupted read, a resize or a part of the prompt
+            // being done interrupts it too and comes back as an unknown key so the line is just
+            // redrawn
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                if signals::resized() | signals::woken() {
+                    return Ok(Key::Unknown);
+                }
+                Key::Char('\x03')
+            },
+            key => key?,
+        };
+
+        let Key::UnknownEscSeq(mut sequence) = key else {
+            return Ok(key);
+        };
+
+        while sequence.len() > 1 && sequence[0] == '[' && sequence.last().is_some_and(|character| ('0'..='?').contains(character)) {
+            match self.term.read_key()? {
+                Key::Char(character) => sequence.push(character),
+                _ => break,
+            }
+        }
+
+        Ok(Key::UnknownEscSeq(sequence))
+    }
+
+    // paste reads the pasted text up to the end of the paste and inserts it as it is, newlines are
+    // kept so nothing runs until Enter is pressed. whatever is pasted past MAX_PASTE is read and
+    // thrown away so none of it is taken as keys. Ctrl-C drops the paste and returns false so a
+    // lost end of the paste cant swallow the input
+    fn paste(&mut self) -> io::Result<bool> {
+        let mut text = String::new();
+
+        loop {
+            match self.read_key()? {
+                Key::UnknownEscSeq(sequence) if sequence.iter().collect::<String>() == PASTE_END => break,
+                Key::Char('\x03') => return Ok(false),
+                _ if text.len() >= MAX_PASTE => {},
+                Key::Char(character) if !character.is_control() => text.push(character),
+                Key::Enter => text.push('\n'),
+                Key::Tab => text.push('\t'),
+                _ => {},
+            }
+        }
+
+        self.menu = None;
+        self.end_search();
+        self.history_prefix = None;
+
+        let start = self.cursor;
+        self.replace(start, start, &text);
+        self.pasted = Some((start, self.cursor));
+
+        Ok(true)
+    }
+
+    fn replace(&mut self, start: usize, end: usize, text: &str) {
+        self.buffer.replace_range(start..end, text);
+        self.cursor = start + text.len();
+    }
+
+    fn complete(&mut self, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        let completion = match completion::complete(&self.buffer[..self.cursor], hash, config, &mut self.help) {
+            Ok(completion) => completion,
+            Err(err) => {
+                return Err(ReadLineError::Completion(err.to_string()));
+            },
+        };
+
+        match completion.candidates.as_slice() {
+            [] => {},
+            [candidate] => {
+                // a unique match is finished off with a space unless it is a directory
+                let mut value = candidate.value.clone();
+                if !value.ends_with('/') {
+                    value.push(' ');
+                }
+
+                self.replace(completion.start, self.cursor, &value);
+            },
+            candidates => {
+                let prefix = completion::common_prefix(candidates);
+                if prefix.len() > self.cursor - completion.start {
+                    self.replace(completion.start, self.cursor, &prefix);
+                }
+
+                self.menu = Some(Menu::new(completion.start, self.cursor, completion.candidates));
+            },
         }
 
         Ok(())
     }
 
-    fn history_get(&mut self, history_index: usize) {
-        if !self.history.is_empty() {
-            self.buffer = self.history[history_index].clone();
-            self.cursor = self.history[history_index].len();
+    // menu_key handles keys while a completion menu is open, it returns false when the key should
+    // be handled by the editor instead, which also closes the menu
+    fn menu_key(&mut self, key: &Key) -> bool {
+        let Some(menu) = &mut self.menu else {
+            return false;
+        };
+
+        let columns = menu.columns(self.term.size().1 as usize);
+
+        let (amount, forward) = match key {
+            Key::Tab => (1, true),
+            Key::BackTab => (1, false),
+            Key::ArrowRight if menu.visible => (1, true),
+            Key::ArrowLeft if menu.visible => (1, false),
+            Key::ArrowDown if menu.visible => (columns, true),
+            Key::ArrowUp if menu.visible => (columns, false),
+            Key::Enter if menu.selected.is_some() => {
+                // enter accepts the selection instead of running the line
+                self.menu = None;
+                return true;
+            },
+            _ => {
+                self.menu = None;
+                return false;
+            },
+        };
+
+        // the first tab after an ambiguous completion only shows the menu
+        if !menu.visible && *key == Key::Tab {
+            menu.visible = true;
+            return true;
+        }
+        menu.visible = true;
+
+        let (start, end) = (menu.start, menu.end);
+        let value = menu.step(amount, forward).value.clone();
+        menu.end = start + value.len();
+
+        self.replace(start, end, &value);
+        true
+    }
+
+    pub fn input(&mut self, prompt: Prompt, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        self.prompt = prompt;
+
+        if let Err(err) = signals::ignore_sigint() {
+            return Err(ReadLineError::Read(err.to_string()));
+        }
+
+        // bracketed paste is only on while the line is read so commands dont get the sequences
+        print!("\x1b[?2004h");
+        let result = self.read_line(hash, config);
+        print!("\x1b[?2004l");
+        self.flush()?;
+
+        if let Err(err) = signals::restore_sigint() {
+            return Err(ReadLineError::Read(err.to_string()));
+        }
+
+        result
+    }
+
+    // abort throws away the line after Ctrl-C and starts over on a fresh line
+    fn abort(&mut self, prompt: &str, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        self.keymap.cancel();
+        self.menu = None;
+        self.search = None;
+        self.finish(prompt, "^C", hash, config)?;
+
+        self.buffer = String::new();
+        self.cursor = 0;
+        self.history_index = self.history.len();
+        self.history_prefix = None;
+        self.undo.reset(&self.buffer, self.cursor);
+
+        if let Some(vi) = &mut self.vi {
+            vi.reset();
+        }
+        Ok(())
+    }
+
+    // mode returns the vi mode the line is edited in or None in emacs mode
+    pub fn mode(&self) -> Option<Mode> {
+        self.vi.as_ref().map(|vi| vi.mode)
+    }
+
+    // indicator puts the vi mode indicator from the config in front of the prompt
+    fn indicator(&self, prompt: &str, config: &Config) -> String {
+        match self.mode() {
+            Some(Mode::Insert) => format!("{}{prompt}", config.vi_insert_indicator),
+            Some(Mode::Normal) => format!("{}{prompt}", config.vi_normal_indicator),
+            None => prompt.to_string(),
+        }
+    }
+
+    fn vi_key(&mut self, key: Key) -> Option<Key> {
+        let Some(vi) = self.vi.as_mut() else {
+            return Some(key);
+        };
+        let key = vi.key(key, &mut self.buffer, &mut self.cursor);
+
+        // `u` and Ctrl-R go through the same edit history as the emacs bindings
+        if let Some((step, count)) = vi.take_step() {
+            for _ in 0..count {
+                let state = match step {
+                    Step::Undo => self.undo.undo(),
+                    Step::Redo => self.undo.redo(),
+                };
+                let Some((buffer, cursor)) = state else {
+                    break;
+                };
+                self.buffer = buffer;
+                self.cursor = cursor;
+            }
         }
+
+        key
     }
 
-    pub fn input(&mut self, prompt: &str) -> Result<(), ReadLineError> {
+    // start gets ready to read a new line
+    fn start(&mut self, config: &Config) -> Result<(), ReadLineError> {
+        if std::mem::take(&mut self.resume) {
+            return Ok(());
+        }
+
         self.buffer = String::new();
         self.cursor = 0;
+        self.drawn.clear();
 
-        let mut history_index = self.history.len();
+        // a resize or a wake while no line was read doesnt matter anymore
+        signals::resized();
+        signals::woken();
+
+        match config.editing {
+            Editing::Vi => self.vi.get_or_insert_with(Vi::new).reset(),
+            Editing::Emacs => self.vi = None,
+        }
+
+        self.update_history(config)?;
+        self.history_index = self.history.len();
+        self.history_prefix = None;
+        self.undo.reset(&self.buffer, self.cursor);
+        Ok(())
+    }
+
+    fn read_line(&mut self, hash: &mut CommandHash, config: &Config) -> Result<(), ReadLineError> {
+        self.start(config)?;
+        let mut typed = None;
 
         loop {
-            self.output(prompt)?;
+            // whatever the last key did to the line goes into the edit history, a whole vi insert
+            // is one change
+            self.undo.group(self.mode() == Some(Mode::Insert));
+            self.undo.update(&self.buffer, self.cursor, typed.take());
 
-            self.render_cursor()?;
+            self.prompt.update();
+            let prompt = &self.indicator(&self.prompt.left, config);
+            self.output(prompt, hash, config)?;
 
-            let key = match self.term.read_key() {
+            let key = match self.read_key() {
                 Ok(character) => character,
                 Err(err) => {
-                    match err.kind() {
-                        io::ErrorKind::Interrupted => {
-                            return Ok(());
-                        },
-                        _ => {
-                            return Err(ReadLineError::Read(err.to_string()));
-                        },
-                    }
+                    return Err(ReadLineError::Read(err.to_string()));
                 },
             };
 
-            match key {
-                Key::Tab => {
-                    let mut path = self.buffer.split(" ").collect::<Vec<&str>>();
-                    if !path.is_empty() {
-                        let completed = match completion::complete(path[path.len() - 1]) {
-                            Ok(buf) => buf,
-                            Err(err) => {
-                                return Err(ReadLineError::Completion(err.to_string()));
-                            },
-                        };
-
-                        path.pop();
-                        path.push(&completed);
-
-                        self.buffer = path.join(" ");
-                        self.cursor = self.buffer.len();
-                    }
-                },
-                Key::ArrowUp => {
-                    if history_index != 0 {
-                        history_index -= 1;
-                    }
-                    self.history_get(history_index);
-                },
-                Key::ArrowDown => {
-                    if self.history.len() > history_index + 1 {
-                        history_index += 1;
-                        self.history_get(history_index);
-                    } else {
-                        history_index = self.history.len();
-                        self.buffer = String::new();
-                        self.cursor = 0;
-                    }
-                },
-                Key::ArrowLeft => {
-                    self.move_cursor(Direction::Left);
-                },
-                Key::ArrowRight => {
-                    self.move_cursor(Direction::Right);
+            self.pasted = None;
+
+            if key == Key::Char('\x03') {
+                self.abort(prompt, hash, config)?;
+                continue;
+            }
+
+            if matches!(&key, Key::UnknownEscSeq(sequence) if sequence.iter().collect::<String>() == PASTE_START) {
+                match self.paste() {
+                    Ok(true) => {},
+                    Ok(false) => self.abort(prompt, hash, config)?,
+                    Err(err) => {
+                        return Err(ReadLineError::Read(err.to_string()));
+                    },
+                }
+                continue;
+            }
+
+            // the terminal was resized or the prompt changed, the line is drawn again
+            if key == Key::Unknown {
+                continue;
+            }
+
+            if self.search_key(&key) || self.menu_key(&key) {
+                continue;
+            }
+
+            let Some(key) = self.vi_key(key) else {
+                continue;
+            };
+
+            let action = match self.keymap.lookup(&key) {
+                Lookup::Pending => continue,
+                Lookup::Found(Binding::Action(action)) => Some(action),
+                Lookup::Found(Binding::Command(command)) => {
+                    self.command = Some(command);
+                    self.resume = true;
+                    return Ok(());
                 },
-                Key::Backspace => {
-                    if !self.buffer.is_empty() {
-                        self.backspace();
+                Lookup::Unbound(single) => {
+                    if single {
+                        typed = self.self_insert(&key);
                     }
+                    None
                 },
-                Key::Enter => {
-                    self.history.push(self.buffer.clone());
-                    println!(""); // Newline
+            };
+
+            // starting a walk or a search also takes in what other sessions ran while the line
+            // was being typed
+            let starts = matches!(action, Some(Action::PreviousHistory) if self.history_prefix.is_none())
+                || matches!(action, Some(Action::HistorySearchBackward | Action::HistorySearchForward));
+            if starts {
+                self.update_history(config)?;
+            }
+
+            // editing the line starts a new history walk with whatever the line is then
+            if !matches!(action, Some(Action::PreviousHistory | Action::NextHistory)) {
+                self.history_prefix = None;
+            }
+
+            if let Some(action) = action {
+                if self.perform(action, prompt, hash, config)? {
                     break;
-                },
-                Key::Char(character) => {
-                    self.insert(&character);
-
-                    match character {
-                        '\u{4}' => {
-                            println!("beta ^D");
-                            process::exit(1);
-                        },
-                        '\u{1a}' => {
-                            println!("beta ^Z");
-                            process::exit(1);
-                        },
-                        _ => {},
-                    }
-                    self.cursor += 1;
-                },
-                _ => {},
+                }
             }
         }
 
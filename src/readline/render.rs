@@ -0,0 +1,42 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::grapheme;
+
+
+// position returns the row and column the terminal cursor ends up at when the text is printed from
+// the start of a row, a character that doesnt fit on a row anymore goes onto the next one
+//
+// a row that is filled up exactly leaves the cursor behind its last column like terminals do, the
+// column is the width then and the next character or newline is what moves on to the next row
+pub fn position(text: &str, width: usize) -> (usize, usize) {
+    let width = width.max(1);
+    let (mut row, mut column) = (0, 0);
+
+    for grapheme in text.graphemes(true) {
+        if grapheme == "\n" || grapheme == "\r\n" {
+            row += 1;
+            column = 0;
+            continue;
+        }
+
+        let columns = grapheme::width(grapheme);
+        if column + columns > width {
+            row += 1;
+            column = 0;
+        }
+        column += columns;
+    }
+
+    (row, column)
+}
+
+// cursor returns where the cursor is shown after the text, behind a filled up row it is at the
+// start of the next one
+pub fn cursor(text: &str, width: usize) -> (usize, usize) {
+    let (row, column) = position(text, width);
+    if column >= width.max(1) {
+        return (row + 1, 0);
+    }
+
+    (row, column)
+}
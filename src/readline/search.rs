@@ -0,0 +1,109 @@
+use console::style;
+
+use crate::history::History;
+
+
+// Search is an incremental history search started with Ctrl-R or Ctrl-S, while it is active the
+// buffer shows the entry that matched and the cursor sits on the match
+pub struct Search {
+    pub query: String,
+    pub forward: bool,
+    pub failed: bool,
+    // index is the history entry the current match is in and position is where in it
+    pub index: usize,
+    pub position: usize,
+    // original is the buffer and cursor from before the search so Ctrl-G can restore them
+    pub original: (String, usize),
+}
+
+impl Search {
+    pub fn new(forward: bool, history: &History, buffer: &str, cursor: usize) -> Search {
+        Search {
+            query: String::new(),
+            forward,
+            failed: false,
+            index: history.len(),
+            position: 0,
+            original: (buffer.to_string(), cursor),
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        let failed = if self.failed { "failed " } else { "" };
+        let direction = if self.forward { "fwd" } else { "reverse" };
+
+        format!("({failed}{direction}-i-search)`{}': ", self.query)
+    }
+
+    // highlight returns the line with the matched part of it emphasized
+    pub fn highlight(&self, line: &str) -> String {
+        if self.query.is_empty() || self.failed || !line.is_char_boundary(self.position) {
+            return line.to_string();
+        }
+
+        let end = self.position + self.query.len();
+        if end > line.len() {
+            return line.to_string();
+        }
+
+        format!("{}{}{}", &line[..self.position], style(&line[self.position..end]).reverse(), &line[end..])
+    }
+
+    fn step(&self, index: usize, len: usize) -> Option<usize> {
+        if self.forward {
+            (index + 1 < len).then_some(index + 1)
+        } else {
+            index.checked_sub(1)
+        }
+    }
+
+    // find moves to the first entry at or after from in the direction of the search that contains
+    // the query, when nothing matches the search is marked as failed and stays where it was
+    fn find(&mut self, history: &History, from: Option<usize>) -> bool {
+        let mut from = from;
+
+        while let Some(index) = from {
+            let found = history.get(index).and_then(|entry| {
+                if self.forward {
+                    entry.line.find(&self.query)
+                } else {
+                    entry.line.rfind(&self.query)
+                }
+            });
+
+            if let Some(position) = found {
+                self.index = index;
+                self.position = position;
+                self.failed = false;
+                return true;
+            }
+
+            from = self.step(index, history.len());
+        }
+
+        self.failed = true;
+        false
+    }
+
+    // search looks for the query again after it changed, the current match is kept if it still
+    // contains the query
+    pub fn search(&mut self, history: &History) -> bool {
+        if self.query.is_empty() || history.is_empty() {
+            self.failed = false;
+            return false;
+        }
+
+        self.find(history, Some(self.index.min(history.len() - 1)))
+    }
+
+    // again skips to the next match in the direction of the search, this is what repeated
+    // Ctrl-R and Ctrl-S do
+    pub fn again(&mut self, history: &History) -> bool {
+        if self.query.is_empty() || self.index >= history.len() {
+            return self.search(history);
+        }
+
+        let from = self.step(self.index, history.len());
+        self.find(history, from)
+    }
+}
@@ -0,0 +1,108 @@
+// Undo is the edit history of the line, it is kept by comparing the line after every key with how
+// it was before so every way of changing the line can be undone
+#[derive(Default)]
+pub struct Undo {
+    done: Vec<(String, usize)>,
+    undone: Vec<(String, usize)>,
+    // current is the line and cursor after the last key
+    current: (String, usize),
+    // typing is set while characters are typed one after another, they are undone a word at a time
+    typing: bool,
+    // grouped is set while every change should be undone together, like a vi insert
+    grouped: bool,
+    // started is set once the group has its first change in the history
+    started: bool,
+}
+
+impl Undo {
+    pub fn new() -> Undo {
+        Undo {
+            done: Vec::new(),
+            undone: Vec::new(),
+            current: (String::new(), 0),
+            typing: false,
+            grouped: false,
+            started: false,
+        }
+    }
+
+    pub fn reset(&mut self, buffer: &str, cursor: usize) {
+        self.done.clear();
+        self.undone.clear();
+        self.current = (buffer.to_string(), cursor);
+        self.typing = false;
+        self.started = false;
+    }
+
+    // group starts or ends a group of changes that are undone in one step
+    pub fn group(&mut self, grouped: bool) {
+        self.started &= grouped;
+        self.grouped = grouped;
+    }
+
+    // update takes in the line after a key, typed is the character the key inserted if it did
+    pub fn update(&mut self, buffer: &str, cursor: usize, typed: Option<char>) {
+        if self.current.0 == buffer {
+            // moving the cursor ends the word being typed
+            self.current.1 = cursor;
+            self.typing &= typed.is_some();
+            return;
+        }
+
+        // whitespace starts a new word so typing a line is undone word by word
+        let joined = (self.grouped && self.started)
+            || (self.typing && typed.is_some_and(|character| !character.is_whitespace()));
+        let previous = std::mem::replace(&mut self.current, (buffer.to_string(), cursor));
+        if !joined {
+            self.done.push(previous);
+        }
+
+        self.undone.clear();
+        self.typing = typed.is_some();
+        self.started = self.grouped;
+    }
+
+    pub fn undo(&mut self) -> Option<(String, usize)> {
+        let state = self.done.pop()?;
+        self.undone.push(std::mem::replace(&mut self.current, state.clone()));
+        self.typing = false;
+        self.started = false;
+
+        Some(state)
+    }
+
+    pub fn redo(&mut self) -> Option<(String, usize)> {
+        let state = self.undone.pop()?;
+        self.done.push(std::mem::replace(&mut self.current, state.clone()));
+        self.typing = false;
+        self.started = false;
+
+        Some(state)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_group_is_undone_at_once() {
+        let mut undo = Undo::new();
+        undo.reset("one two", 7);
+
+        // a vi change deletes the word and types a new one in the same insert
+        undo.group(true);
+        undo.update("one ", 4, None);
+        undo.update("one t", 5, Some('t'));
+        undo.update("one t w", 7, Some(' '));
+        undo.group(false);
+        undo.update("one t w", 6, None);
+        undo.update("one t", 5, None);
+
+        assert_eq!(undo.undo(), Some(("one t w".to_string(), 6)));
+        assert_eq!(undo.undo(), Some(("one two".to_string(), 7)));
+        assert_eq!(undo.undo(), None);
+        assert_eq!(undo.redo(), Some(("one t w".to_string(), 6)));
+    }
+}
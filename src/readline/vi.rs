@@ -0,0 +1,669 @@
+use console::Key;
+
+use super::edit;
+use super::grapheme;
+
+
+// counts are capped at this, no line is long enough for a bigger count to make a difference
+const MAX_COUNT: usize = 100_000;
+
+// what `p` and `P` put into the line is capped at this many bytes however big the count is
+const MAX_PUT: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Insert,
+    Normal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    // the flag is set for the WORD motions that only stop at whitespace
+    WordForward(bool),
+    WordBackward(bool),
+    WordEnd(bool),
+    Start,
+    FirstNonBlank,
+    End,
+    // the kind is one of f, t, F and T
+    Find(char, char),
+    RepeatFind(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Move(Motion),
+    // None stands for the whole line like `dd`
+    Operate(Operator, Option<Motion>),
+    Replace(char),
+    // everything else is a single key like `x` or `p`
+    Key(char),
+}
+
+// Step is an undo or redo vi asks the line editor for, the edit history is shared with emacs mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Undo,
+    Redo,
+}
+
+enum Parsed<T> {
+    Incomplete,
+    Invalid,
+    Done(T),
+}
+
+// count splits a leading count off of the keys, a leading zero is the `0` motion and not a count
+fn count(keys: &str) -> (Option<usize>, &str) {
+    let digits = keys.find(|character: char| !character.is_ascii_digit()).unwrap_or(keys.len());
+    if keys.starts_with('0') || digits == 0 {
+        return (None, keys);
+    }
+
+    // only a count too big for usize fails to parse
+    let count = keys[..digits].parse::<usize>().map_or(MAX_COUNT, |count| count.min(MAX_COUNT));
+    (Some(count), &keys[digits..])
+}
+
+fn motion(keys: &str) -> Parsed<Motion> {
+    let mut characters = keys.chars();
+    let Some(key) = characters.next() else {
+        return Parsed::Incomplete;
+    };
+
+    let motion = match key {
+        'h' => Motion::Left,
+        'l' | ' ' => Motion::Right,
+        'w' | 'W' => Motion::WordForward(key == 'W'),
+        'b' | 'B' => Motion::WordBackward(key == 'B'),
+        'e' | 'E' => Motion::WordEnd(key == 'E'),
+        '0' => Motion::Start,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::End,
+        ';' | ',' => Motion::RepeatFind(key == ','),
+        'f' | 't' | 'F' | 'T' => match characters.next() {
+            Some(target) => Motion::Find(key, target),
+            None => return Parsed::Incomplete,
+        },
+        _ => return Parsed::Invalid,
+    };
+
+    Parsed::Done(motion)
+}
+
+// parse reads a normal mode command like `3dw`, `fx` or `p` from the keys typed so far
+fn parse(keys: &str) -> Parsed<(usize, Action)> {
+    let (first_count, keys) = count(keys);
+    let Some(key) = keys.chars().next() else {
+        return Parsed::Incomplete;
+    };
+
+    let action = match key {
+        'd' | 'c' | 'y' => {
+            let operator = match key {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                _ => Operator::Yank,
+            };
+
+            // the counts before the operator and before the motion multiply like in vi
+            let (second_count, rest) = count(&keys[1..]);
+            let count = first_count.unwrap_or(1).saturating_mul(second_count.unwrap_or(1)).min(MAX_COUNT);
+
+            if rest.starts_with(key) {
+                return Parsed::Done((count, Action::Operate(operator, None)));
+            }
+
+            return match motion(rest) {
+                Parsed::Done(motion) => Parsed::Done((count, Action::Operate(operator, Some(motion)))),
+                Parsed::Incomplete => Parsed::Incomplete,
+                Parsed::Invalid => Parsed::Invalid,
+            };
+        },
+        'r' => match keys[1..].chars().next() {
+            Some(character) => Action::Replace(character),
+            None => return Parsed::Incomplete,
+        },
+        'x' | 'X' | 'p' | 'P' | 'u' | '.' | 'i' | 'a' | 'I' | 'A' | 's' | 'S' | 'C' | 'D' | '~' | 'j' | 'k' => Action::Key(key),
+        _ => match motion(keys) {
+            Parsed::Done(motion) => Action::Move(motion),
+            Parsed::Incomplete => return Parsed::Incomplete,
+            Parsed::Invalid => return Parsed::Invalid,
+        },
+    };
+
+    Parsed::Done((first_count.unwrap_or(1), action))
+}
+
+// the line is split into graphemes for the normal mode commands so that a count or a motion moves
+// over characters as they are seen, a grapheme is classified by its first character
+fn blank(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(char::is_whitespace)
+}
+
+// class groups characters for the word motions, a word is a run of characters of the same class
+fn class(grapheme: &str, big: bool) -> u8 {
+    let character = grapheme.chars().next().unwrap_or(' ');
+    if character.is_whitespace() {
+        0
+    } else if big || edit::is_word(character) {
+        1
+    } else {
+        2
+    }
+}
+
+fn next_word_start(line: &[String], mut idx: usize, big: bool) -> usize {
+    if let Some(grapheme) = line.get(idx) {
+        let current = class(grapheme, big);
+        while current != 0 && idx < line.len() && class(&line[idx], big) == current {
+            idx += 1;
+        }
+    }
+
+    while idx < line.len() && blank(&line[idx]) {
+        idx += 1;
+    }
+
+    idx
+}
+
+fn previous_word_start(line: &[String], mut idx: usize, big: bool) -> usize {
+    while idx > 0 && blank(&line[idx - 1]) {
+        idx -= 1;
+    }
+
+    if idx == 0 {
+        return 0;
+    }
+
+    let current = class(&line[idx - 1], big);
+    while idx > 0 && class(&line[idx - 1], big) == current {
+        idx -= 1;
+    }
+
+    idx
+}
+
+fn word_end(line: &[String], mut idx: usize, big: bool) -> usize {
+    idx += 1;
+    while idx < line.len() && blank(&line[idx]) {
+        idx += 1;
+    }
+
+    if idx >= line.len() {
+        return line.len().saturating_sub(1);
+    }
+
+    let current = class(&line[idx], big);
+    while idx + 1 < line.len() && class(&line[idx + 1], big) == current {
+        idx += 1;
+    }
+
+    idx
+}
+
+// find looks for the count-th target in the direction of the kind, t and T stop next to it
+fn find(line: &[String], idx: usize, kind: char, target: char, count: usize) -> Option<usize> {
+    let matches = |position: &usize| line[*position].starts_with(target);
+
+    let found = if kind == 'f' || kind == 't' {
+        let start = if kind == 't' { idx + 2 } else { idx + 1 };
+        (start.min(line.len())..line.len()).filter(matches).nth(count - 1)?
+    } else {
+        let end = if kind == 'T' { idx.saturating_sub(1) } else { idx };
+        (0..end).rev().filter(matches).nth(count - 1)?
+    };
+
+    Some(match kind {
+        't' => found - 1,
+        'T' => found + 1,
+        _ => found,
+    })
+}
+
+// split returns the graphemes of the line and which of them the cursor is on
+fn split(buffer: &str, cursor: usize) -> (Vec<String>, usize) {
+    (grapheme::graphemes(buffer), grapheme::graphemes(&buffer[..cursor]).len())
+}
+
+fn to_bytes(line: &[String], idx: usize) -> usize {
+    line[..idx.min(line.len())].iter().map(String::len).sum()
+}
+
+// Vi is the state of the vi editing mode, normal mode commands are collected key by key until they
+// are complete and then applied to the line
+pub struct Vi {
+    pub mode: Mode,
+    pending: String,
+    // register is what was deleted or yanked last, p and P put it back
+    register: String,
+    // step is an undo or redo with its count that the line editor hasnt taken yet
+    step: Option<(Step, usize)>,
+    // last_change is the keys of the last change for `.`, a change that enters insert mode is
+    // recorded until escape so the typed text is repeated too
+    last_change: String,
+    recording: Option<String>,
+    replaying: bool,
+    // last_find is the last f, t, F or T for ; and ,
+    last_find: Option<(char, char)>,
+}
+
+impl Default for Vi {
+    fn default() -> Self {
+        Vi::new()
+    }
+}
+
+impl Vi {
+    pub fn new() -> Vi {
+        Vi {
+            mode: Mode::Insert,
+            pending: String::new(),
+            register: String::new(),
+            step: None,
+            last_change: String::new(),
+            recording: None,
+            replaying: false,
+            last_find: None,
+        }
+    }
+
+    // reset gets ready for a new line, every line starts out in insert mode
+    pub fn reset(&mut self) {
+        self.mode = Mode::Insert;
+        self.pending.clear();
+        self.step = None;
+        self.recording = None;
+    }
+
+    // take_step returns the undo or redo that `u` or Ctrl-R asked for
+    pub fn take_step(&mut self) -> Option<(Step, usize)> {
+        self.step.take()
+    }
+
+    // key handles a key in vi mode, the keys it doesnt use are returned so the line editor can
+    // handle them like in emacs mode, in normal mode j and k come back as the arrow keys
+    pub fn key(&mut self, key: Key, buffer: &mut String, cursor: &mut usize) -> Option<Key> {
+        match self.mode {
+            Mode::Insert => match key {
+                Key::Escape => {
+                    self.escape(buffer, cursor);
+                    None
+                },
+                Key::Char(character) if !character.is_control() => {
+                    if let Some(recording) = &mut self.recording {
+                        recording.push(character);
+                    }
+                    Some(key)
+                },
+                Key::Backspace => {
+                    if let Some(recording) = &mut self.recording {
+                        recording.push('\x7f');
+                    }
+                    Some(key)
+                },
+                _ => Some(key),
+            },
+            Mode::Normal => match key {
+                Key::Char(character) if !character.is_control() => {
+                    self.pending.push(character);
+
+                    let keys = std::mem::take(&mut self.pending);
+                    let translated = match parse(&keys) {
+                        Parsed::Incomplete => {
+                            self.pending = keys;
+                            return None;
+                        },
+                        Parsed::Invalid => None,
+                        Parsed::Done((count, action)) => self.execute(&keys, count, action, buffer, cursor),
+                    };
+
+                    self.clamp(buffer, cursor);
+                    translated
+                },
+                Key::Escape => {
+                    self.pending.clear();
+                    None
+                },
+                Key::Backspace => Some(Key::ArrowLeft),
+                Key::Char('\x12') => {
+                    let keys = std::mem::take(&mut self.pending);
+                    self.step = Some((Step::Redo, count(&keys).0.unwrap_or(1)));
+                    None
+                },
+                _ => {
+                    self.pending.clear();
+                    Some(key)
+                },
+            },
+        }
+    }
+
+    fn escape(&mut self, buffer: &str, cursor: &mut usize) {
+        if let Some(recording) = self.recording.take() {
+            self.last_change = recording + "\x1b";
+        }
+
+        self.mode = Mode::Normal;
+        *cursor = grapheme::previous(buffer, *cursor);
+    }
+
+    // clamp keeps the cursor on a character, in normal mode it cant sit behind the line
+    fn clamp(&self, buffer: &str, cursor: &mut usize) {
+        if self.mode == Mode::Normal && *cursor >= buffer.len() {
+            *cursor = grapheme::previous(buffer, buffer.len());
+        }
+    }
+
+    fn insert(&mut self, keys: &str) {
+        self.mode = Mode::Insert;
+        if !self.replaying {
+            self.recording = Some(keys.to_string());
+        }
+    }
+
+    fn changed(&mut self, keys: &str) {
+        if !self.replaying {
+            self.last_change = keys.to_string();
+        }
+    }
+
+    // target returns where a motion ends up and whether the character there belongs to the range
+    // an operator works on
+    fn target(&mut self, motion: Motion, count: usize, line: &[String], idx: usize) -> Option<(usize, bool)> {
+        let mut position = idx;
+
+        let inclusive = match motion {
+            Motion::Left => {
+                position = idx.saturating_sub(count);
+                false
+            },
+            Motion::Right => {
+                position = idx.saturating_add(count).min(line.len());
+                false
+            },
+            Motion::WordForward(big) => {
+                for _ in 0..count {
+                    position = next_word_start(line, position, big);
+                }
+                false
+            },
+            Motion::WordBackward(big) => {
+                for _ in 0..count {
+                    position = previous_word_start(line, position, big);
+                }
+                false
+            },
+            Motion::WordEnd(big) => {
+                for _ in 0..count {
+                    position = word_end(line, position, big);
+                }
+                true
+            },
+            Motion::Start => {
+                position = 0;
+                false
+            },
+            Motion::FirstNonBlank => {
+                position = line.iter().position(|grapheme| !blank(grapheme)).unwrap_or(line.len());
+                false
+            },
+            Motion::End => {
+                position = line.len().saturating_sub(1);
+                true
+            },
+            Motion::Find(kind, target) => {
+                self.last_find = Some((kind, target));
+                position = find(line, idx, kind, target, count)?;
+                kind == 'f' || kind == 't'
+            },
+            Motion::RepeatFind(reverse) => {
+                let (kind, target) = self.last_find?;
+                let kind = if reverse { swap_case(kind) } else { kind };
+
+                position = find(line, idx, kind, target, count)?;
+                kind == 'f' || kind == 't'
+            },
+        };
+
+        Some((position, inclusive))
+    }
+
+    fn execute(&mut self, keys: &str, count: usize, action: Action, buffer: &mut String, cursor: &mut usize) -> Option<Key> {
+        let (line, idx) = split(buffer, *cursor);
+
+        match action {
+            Action::Move(motion) => {
+                if let Some((position, _)) = self.target(motion, count, &line, idx) {
+                    *cursor = to_bytes(&line, position);
+                }
+            },
+            Action::Operate(operator, motion) => {
+                self.operate(keys, count, operator, motion, buffer, cursor);
+            },
+            Action::Replace(character) => {
+                if idx.saturating_add(count) <= line.len() {
+                    buffer.replace_range(*cursor..to_bytes(&line, idx + count), &character.to_string().repeat(count));
+                    *cursor = to_bytes(&grapheme::graphemes(buffer), idx + count - 1);
+                    self.changed(keys);
+                }
+            },
+            Action::Key('j') => return Some(Key::ArrowDown),
+            Action::Key('k') => return Some(Key::ArrowUp),
+            Action::Key('u') => {
+                self.step = Some((Step::Undo, count));
+            },
+            Action::Key('.') => {
+                self.repeat(keys, buffer, cursor);
+            },
+            Action::Key(key) => {
+                self.simple(keys, key, count, buffer, cursor);
+            },
+        }
+
+        None
+    }
+
+    fn operate(&mut self, keys: &str, count: usize, operator: Operator, motion: Option<Motion>, buffer: &mut String, cursor: &mut usize) {
+        let (line, idx) = split(buffer, *cursor);
+
+        let (start, end) = match motion {
+            None => (0, line.len()),
+            Some(motion) => {
+                // cw changes to the end of the word like ce instead of eating the space after it
+                let motion = match motion {
+                    Motion::WordForward(big) if operator == Operator::Change && line.get(idx).is_some_and(|grapheme| !blank(grapheme)) => Motion::WordEnd(big),
+                    _ => motion,
+                };
+
+                let Some((position, inclusive)) = self.target(motion, count, &line, idx) else {
+                    return;
+                };
+
+                let end = idx.max(position) + usize::from(inclusive);
+                (idx.min(position), end.min(line.len()))
+            },
+        };
+
+        let (start, end) = (to_bytes(&line, start), to_bytes(&line, end));
+        self.register = buffer[start..end].to_string();
+
+        if operator == Operator::Yank {
+            *cursor = start;
+            return;
+        }
+
+        buffer.replace_range(start..end, "");
+        *cursor = start;
+
+        if operator == Operator::Change {
+            self.insert(keys);
+        } else {
+            self.changed(keys);
+        }
+    }
+
+    fn simple(&mut self, keys: &str, key: char, count: usize, buffer: &mut String, cursor: &mut usize) {
+        let (line, idx) = split(buffer, *cursor);
+
+        match key {
+            'x' | 's' if !line.is_empty() || key == 's' => {
+                let end = to_bytes(&line, idx.saturating_add(count));
+                self.register = buffer.drain(*cursor..end).collect();
+            },
+            'X' if idx > 0 => {
+                let start = to_bytes(&line, idx.saturating_sub(count));
+                self.register = buffer.drain(start..*cursor).collect();
+                *cursor = start;
+            },
+            'p' | 'P' if !self.register.is_empty() => {
+                let at = if key == 'p' && !line.is_empty() { to_bytes(&line, idx + 1) } else { *cursor };
+                let text = self.register.repeat(count.min(MAX_PUT / self.register.len()).max(1));
+
+                buffer.insert_str(at, &text);
+                *cursor = grapheme::previous(&text, text.len()) + at;
+            },
+            'D' | 'C' | 'S' => {
+                let start = if key == 'S' { 0 } else { *cursor };
+                self.register = buffer.drain(start..).collect();
+                *cursor = start;
+            },
+            '~' if !line.is_empty() => {
+                let end = idx.saturating_add(count).min(line.len());
+                let toggled = line[idx..end].iter().flat_map(|grapheme| grapheme.chars()).map(swap_case).collect::<String>();
+
+                buffer.replace_range(*cursor..to_bytes(&line, end), &toggled);
+                *cursor = to_bytes(&line, end);
+            },
+            'i' => {},
+            'a' => *cursor = to_bytes(&line, idx + 1),
+            'I' => *cursor = to_bytes(&line, line.iter().position(|grapheme| !blank(grapheme)).unwrap_or(line.len())),
+            'A' => *cursor = buffer.len(),
+            _ => return,
+        }
+
+        if matches!(key, 'i' | 'a' | 'I' | 'A' | 's' | 'S' | 'C') {
+            self.insert(keys);
+        } else {
+            self.changed(keys);
+        }
+    }
+
+    // repeat runs the last change again, a count given to `.` replaces the count of the change
+    fn repeat(&mut self, keys: &str, buffer: &mut String, cursor: &mut usize) {
+        if self.replaying || self.last_change.is_empty() {
+            return;
+        }
+
+        let (count, _) = count(keys);
+        let change = match count {
+            Some(count) => format!("{count}{}", self.last_change.trim_start_matches(|character: char| character.is_ascii_digit() && character != '0')),
+            None => self.last_change.clone(),
+        };
+
+        self.replaying = true;
+        for character in change.chars() {
+            match self.mode {
+                Mode::Normal => {
+                    self.key(Key::Char(character), buffer, cursor);
+                },
+                Mode::Insert => match character {
+                    '\x1b' => self.escape(buffer, cursor),
+                    '\x7f' => {
+                        let start = grapheme::previous(buffer, *cursor);
+                        buffer.drain(start..*cursor);
+                        *cursor = start;
+                    },
+                    _ => {
+                        buffer.insert(*cursor, character);
+                        *cursor += character.len_utf8();
+                    },
+                },
+            }
+        }
+        self.replaying = false;
+    }
+}
+
+fn swap_case(character: char) -> char {
+    if character.is_uppercase() {
+        character.to_lowercase().next().unwrap_or(character)
+    } else {
+        character.to_uppercase().next().unwrap_or(character)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // normal runs the keys in normal mode on the line with the cursor at the start
+    fn normal(line: &str, keys: &str) -> (String, usize) {
+        let mut vi = Vi::new();
+        let mut buffer = line.to_string();
+        let mut cursor = 0;
+
+        vi.key(Key::Escape, &mut buffer, &mut cursor);
+        for character in keys.chars() {
+            vi.key(Key::Char(character), &mut buffer, &mut cursor);
+        }
+
+        (buffer, cursor)
+    }
+
+    #[test]
+    fn counts_multiply() {
+        assert!(matches!(parse("3d2w"), Parsed::Done((6, Action::Operate(Operator::Delete, Some(Motion::WordForward(false)))))));
+        assert!(matches!(parse("2x"), Parsed::Done((2, Action::Key('x')))));
+        assert!(matches!(parse("0"), Parsed::Done((1, Action::Move(Motion::Start)))));
+        assert!(matches!(parse("10"), Parsed::Incomplete));
+    }
+
+    #[test]
+    fn huge_counts_are_capped() {
+        assert_eq!(count("99999999999999999999999w"), (Some(MAX_COUNT), "w"));
+        assert!(matches!(parse("99999999999d99999999999w"), Parsed::Done((MAX_COUNT, _))));
+
+        assert_eq!(normal("one two three", "99999999999d99999999999w").0, "");
+        assert_eq!(normal("one two", "99999999999999999999l").1, 6);
+        assert_eq!(normal("one two", "99999999999999999999x").0, "");
+        assert_eq!(normal("one two", "99999999999999999999~").0, "ONE TWO");
+    }
+
+    #[test]
+    fn huge_puts_are_capped() {
+        let (buffer, _) = normal("ab", "yl9999999999999999999p");
+        assert!(buffer.len() <= MAX_PUT + 2);
+        assert!(buffer.starts_with("aaa"));
+    }
+
+    #[test]
+    fn undo_is_left_to_the_editor() {
+        let mut vi = Vi::new();
+        let mut buffer = "one two".to_string();
+        let mut cursor = 0;
+
+        vi.key(Key::Escape, &mut buffer, &mut cursor);
+        for key in [Key::Char('d'), Key::Char('w'), Key::Char('2'), Key::Char('u')] {
+            vi.key(key, &mut buffer, &mut cursor);
+        }
+        assert_eq!(buffer, "two");
+        assert_eq!(vi.take_step(), Some((Step::Undo, 2)));
+
+        vi.key(Key::Char('3'), &mut buffer, &mut cursor);
+        vi.key(Key::Char('\x12'), &mut buffer, &mut cursor);
+        assert_eq!(vi.take_step(), Some((Step::Redo, 3)));
+        assert_eq!(vi.take_step(), None);
+    }
+}
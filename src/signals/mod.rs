@@ -1,18 +1,109 @@
-use nix::sys::signal;
+use nix::sys::signal::{self, SigSet, SigmaskHow, Signal};
+use nix::sys::pthread::{self, Pthread};
+use nix::poll::{self, PollFd, PollFlags};
+use nix::errno::Errno;
 use nix::libc;
 
+use std::os::fd::BorrowedFd;
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+// RESIZED is set when the terminal changes size so the line being edited can be redrawn
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+// WOKEN is set when a part of the prompt is done in the background so the line is redrawn with it
+static WOKEN: AtomicBool = AtomicBool::new(false);
 
 
 extern fn handle_sigint(_: i32, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
     println!("^C");
 }
 
+extern "C" fn handle_sigwinch(_: i32, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_sigusr1(_: i32, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    WOKEN.store(true, Ordering::SeqCst);
+}
+
 extern fn handle_sighup(_: i32, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
     println!("sighup");
     process::exit(1);
 }
 
+// while a line is read Ctrl-C is handled by the line editor, console still raises SIGINT when it
+// reads one so the signal is ignored until the line is done
+pub fn ignore_sigint() -> Result<(), Box<dyn std::error::Error>> {
+    let ignore_action = signal::SigAction::new(signal::SigHandler::SigIgn,
+                                               signal::SaFlags::empty(),
+                                               signal::SigSet::empty());
+
+    unsafe {
+        signal::sigaction(signal::SIGINT, &ignore_action)?;
+    }
+
+    Ok(())
+}
+
+pub fn restore_sigint() -> Result<(), Box<dyn std::error::Error>> {
+    let sigint_action = signal::SigAction::new(signal::SigHandler::SigAction(handle_sigint),
+                                               signal::SaFlags::empty(),
+                                               signal::SigSet::empty());
+
+    unsafe {
+        signal::sigaction(signal::SIGINT, &sigint_action)?;
+    }
+
+    Ok(())
+}
+
+// resized tells if the terminal was resized since the last time it was asked
+pub fn resized() -> bool {
+    RESIZED.swap(false, Ordering::SeqCst)
+}
+
+// woken tells if the line editor was woken up since the last time it was asked
+pub fn woken() -> bool {
+    WOKEN.swap(false, Ordering::SeqCst)
+}
+
+// wait blocks until there is input on the terminal and tells if there is, it returns false when
+// the line editor was woken or a signal came in. SIGUSR1 is blocked from when WOKEN is checked
+// until ppoll unblocks it so a wake in between cant be lost
+pub fn wait(terminal: BorrowedFd) -> nix::Result<bool> {
+    let mut wake = SigSet::empty();
+    wake.add(Signal::SIGUSR1);
+    let blocked = wake.thread_swap_mask(SigmaskHow::SIG_BLOCK)?;
+
+    let mut unblocked = blocked;
+    unblocked.remove(Signal::SIGUSR1);
+
+    let ready = if WOKEN.load(Ordering::SeqCst) {
+        Ok(false)
+    } else {
+        match poll::ppoll(&mut [PollFd::new(&terminal, PollFlags::POLLIN)], None, Some(unblocked)) {
+            Ok(_) => Ok(true),
+            Err(Errno::EINTR) => Ok(false),
+            Err(err) => Err(err),
+        }
+    };
+
+    blocked.thread_set_mask()?;
+    ready
+}
+
+// thread returns the thread that is calling, that is the one wake has to interrupt
+pub fn thread() -> Pthread {
+    pthread::pthread_self()
+}
+
+// wake interrupts the read of the line editor running on the thread so the line gets redrawn
+pub fn wake(thread: Pthread) {
+    let _ = pthread::pthread_kill(thread, signal::SIGUSR1);
+}
+
 pub fn handle_signals() -> Result<(), Box<dyn std::error::Error>> {
     let sigint_action = signal::SigAction::new(signal::SigHandler::SigAction(handle_sigint),
                                                signal::SaFlags::empty(),
@@ -22,9 +113,22 @@ pub fn handle_signals() -> Result<(), Box<dyn std::error::Error>> {
                                                signal::SaFlags::empty(),
                                                signal::SigSet::empty());
 
+    // SA_RESTART is left out so a resize interrupts the read of the line editor
+    let sigwinch_action = signal::SigAction::new(signal::SigHandler::SigAction(handle_sigwinch),
+                                                 signal::SaFlags::empty(),
+                                                 signal::SigSet::empty());
+
+    // a wake only has to interrupt the read of the line editor, poll is never restarted so
+    // SA_RESTART keeps it from interrupting anything else
+    let sigusr1_action = signal::SigAction::new(signal::SigHandler::SigAction(handle_sigusr1),
+                                                signal::SaFlags::SA_RESTART,
+                                                signal::SigSet::empty());
+
     unsafe {
         signal::sigaction(signal::SIGINT, &sigint_action)?;
         signal::sigaction(signal::SIGHUP, &sighup_action)?;
+        signal::sigaction(signal::SIGWINCH, &sigwinch_action)?;
+        signal::sigaction(signal::SIGUSR1, &sigusr1_action)?;
     }
 
     Ok(())